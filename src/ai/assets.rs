@@ -0,0 +1,199 @@
+//! Rule sets loaded from files under `assets/`, reloaded whenever the file changes.
+
+use super::rules::RuleSet;
+use super::validation::{self, Severity};
+use super::{dsl, AiPlayer, AiRuleSet};
+use crate::arena::areas::AreaMap;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use std::fmt;
+
+/// Needs `AssetPlugin`. Files are only reloaded on native targets, where bevy's
+/// `file_watcher` feature is enabled.
+pub struct RuleSetAssetPlugin;
+
+impl Plugin for RuleSetAssetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<RuleSet>()
+            .init_asset_loader::<RuleSetLoader>()
+            .add_systems(Update, (load_rule_files, apply_rule_files).chain());
+    }
+}
+
+/// Rule file of an AI, relative to the asset folder: JSON if it ends in `.rules.json`, the
+/// text syntax of `dsl` if it ends in `.rules`. Replaces the `AiRuleSet` once loaded and
+/// every time the file changes. Invalid files are ignored and the AI keeps its rules.
+#[derive(Component)]
+#[require(AiRuleSet)]
+pub struct AiRuleFile(pub String);
+
+#[derive(Component)]
+struct RuleFileHandle(Handle<RuleSet>);
+
+#[derive(Default, TypePath)]
+pub struct RuleSetLoader;
+
+#[derive(Debug)]
+pub enum RuleSetLoaderError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Parse(dsl::ParseError),
+}
+
+impl fmt::Display for RuleSetLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleSetLoaderError::Io(e) => write!(f, "could not read rule file: {}", e),
+            RuleSetLoaderError::Json(e) => write!(f, "invalid rule set JSON: {}", e),
+            RuleSetLoaderError::Parse(e) => write!(f, "invalid rule file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RuleSetLoaderError {}
+
+impl AssetLoader for RuleSetLoader {
+    type Asset = RuleSet;
+    type Settings = ();
+    type Error = RuleSetLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(RuleSetLoaderError::Io)?;
+
+        let is_text = load_context
+            .path()
+            .path()
+            .extension()
+            .is_some_and(|extension| extension == dsl::EXTENSION);
+        if is_text {
+            let text = String::from_utf8(bytes).map_err(|e| {
+                RuleSetLoaderError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+            })?;
+            dsl::parse(&text).map_err(RuleSetLoaderError::Parse)
+        } else {
+            serde_json::from_slice(&bytes).map_err(RuleSetLoaderError::Json)
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rules.json", "rules"]
+    }
+}
+
+fn load_rule_files(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    query: Query<(Entity, &AiRuleFile), Changed<AiRuleFile>>,
+) {
+    for (entity, file) in &query {
+        let handle = asset_server.load(file.0.clone());
+        commands.entity(entity).insert(RuleFileHandle(handle));
+    }
+}
+
+fn apply_rule_files(
+    mut asset_events: MessageReader<AssetEvent<RuleSet>>,
+    rule_sets: Res<Assets<RuleSet>>,
+    area_map: Res<AreaMap>,
+    mut ai_query: Query<(&Name, &AiRuleFile, Ref<RuleFileHandle>, &mut AiRuleSet), With<AiPlayer>>,
+) {
+    // A reload can report the same asset as both modified and loaded.
+    let mut changed = Vec::new();
+    for event in asset_events.read() {
+        if let AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } = event {
+            if !changed.contains(id) {
+                changed.push(*id);
+            }
+        }
+    }
+
+    let mut issues = HashMap::new();
+    for (name, file, handle, mut ai_rule_set) in ai_query.iter_mut() {
+        let id = handle.0.id();
+        // A file another AI loaded before sends no event, so new handles are applied as is.
+        if !handle.is_added() && !changed.contains(&id) {
+            continue;
+        }
+        let Some(rule_set) = rule_sets.get(id) else {
+            continue;
+        };
+
+        // Validate once per file, logging against the first AI that uses it.
+        let issues = issues.entry(id).or_insert_with(|| {
+            let issues = rule_set.validate(&area_map);
+            for issue in issues.iter().filter(|i| i.severity == Severity::Warning) {
+                warn!("Rule file {}: {}", file.0, issue);
+            }
+            for issue in issues.iter().filter(|i| i.severity == Severity::Error) {
+                warn!("Ignoring rule file {}: {}", file.0, issue);
+            }
+            issues
+        });
+        if validation::has_errors(issues) {
+            continue;
+        }
+
+        ai_rule_set.0 = rule_set.clone();
+        info!(
+            "AI {} loaded rule set from {} ({} rules)",
+            name,
+            file.0,
+            rule_set.rules.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), RuleSetAssetPlugin))
+            .init_resource::<AreaMap>();
+        app
+    }
+
+    fn spawn_ai(app: &mut App, file: &str) -> Entity {
+        app.world_mut()
+            .spawn((
+                AiPlayer,
+                Name::new(file.to_string()),
+                AiRuleFile(file.to_string()),
+            ))
+            .id()
+    }
+
+    fn rule_count(app: &App, entity: Entity) -> usize {
+        app.world().get::<AiRuleSet>(entity).unwrap().0.rules.len()
+    }
+
+    #[test]
+    fn already_loaded_file_applies_to_new_ai() {
+        let mut app = app();
+        let first = spawn_ai(&mut app, "ai/turret_only.rules.json");
+        for _ in 0..1000 {
+            app.update();
+            if rule_count(&app, first) > 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(rule_count(&app, first) > 0, "the rule file never loaded");
+
+        let second = spawn_ai(&mut app, "ai/turret_only.rules.json");
+        app.update();
+        assert_eq!(rule_count(&app, second), rule_count(&app, first));
+    }
+}
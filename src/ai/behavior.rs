@@ -0,0 +1,364 @@
+//! Behavior trees, an alternative to `RuleSet` for AIs that need to run steps in order.
+//!
+//! Leaves reuse `Condition` and `Action`. A tree is ticked from the root once per
+//! simulation tick; `Selector`, `Sequence`, `Parallel` and `Repeat` remember their progress
+//! between ticks in a `BehaviorMemory`, so a running sequence continues where it left off.
+
+use super::rules::{Action, Condition};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeStatus {
+    Running,
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Node {
+    /// Runs children in order until one succeeds. Fails if all fail.
+    Selector(Vec<Node>),
+    /// Runs children in order until one fails. Succeeds if all succeed.
+    Sequence(Vec<Node>),
+    /// Runs all children every tick, each until it finishes. Succeeds once
+    /// `success_threshold` children succeeded, fails once that can't happen anymore.
+    Parallel {
+        success_threshold: usize,
+        children: Vec<Node>,
+    },
+    /// Swaps success and failure.
+    Inverter(Box<Node>),
+    /// Runs the child again after every success, `times` times in total or forever if
+    /// `None`. Fails as soon as the child fails. One run per tick at most.
+    Repeat {
+        times: Option<u32>,
+        child: Box<Node>,
+    },
+    /// Succeeds if the condition holds, fails otherwise.
+    Condition(Condition),
+    /// Executes the action every tick until it succeeds or fails, see `execute_action`.
+    Action(Action),
+}
+
+impl Node {
+    /// Wire names of all variants, advertised to the server during the handshake.
+    pub const VARIANTS: &'static [&'static str] = &[
+        "Selector",
+        "Sequence",
+        "Parallel",
+        "Inverter",
+        "Repeat",
+        "Condition",
+        "Action",
+    ];
+
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            Node::Selector(_) => "Selector",
+            Node::Sequence(_) => "Sequence",
+            Node::Parallel { .. } => "Parallel",
+            Node::Inverter(_) => "Inverter",
+            Node::Repeat { .. } => "Repeat",
+            Node::Condition(_) => "Condition",
+            Node::Action(_) => "Action",
+        }
+    }
+
+    pub fn children(&self) -> &[Node] {
+        match self {
+            Node::Selector(children)
+            | Node::Sequence(children)
+            | Node::Parallel { children, .. } => children,
+            Node::Inverter(child) | Node::Repeat { child, .. } => std::slice::from_ref(&**child),
+            Node::Condition(_) | Node::Action(_) => &[],
+        }
+    }
+
+    /// Calls `f` on this node and every descendant in pre-order, with the index of the
+    /// node in that order.
+    pub fn visit(&self, f: &mut impl FnMut(usize, &Node)) {
+        fn walk(node: &Node, index: &mut usize, f: &mut impl FnMut(usize, &Node)) {
+            f(*index, node);
+            *index += 1;
+            for child in node.children() {
+                walk(child, index, f);
+            }
+        }
+        walk(self, &mut 0, f);
+    }
+
+    /// Number of nodes in this subtree, itself included.
+    pub fn size(&self) -> usize {
+        1 + self.children().iter().map(Node::size).sum::<usize>()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BehaviorTree {
+    pub root: Node,
+}
+
+/// Progress of the nodes of one tree, indexed in `Node::visit` order.
+#[derive(Debug, Clone, Default)]
+pub struct BehaviorMemory {
+    nodes: Vec<NodeMemory>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct NodeMemory {
+    /// Running child of a `Selector` or `Sequence`, finished runs of a `Repeat`.
+    cursor: usize,
+    /// Result of a finished child of a `Parallel`.
+    finished: Option<NodeStatus>,
+}
+
+impl BehaviorMemory {
+    /// Forgets all progress, e.g. after the tree was replaced.
+    pub fn reset(&mut self) {
+        self.nodes.clear();
+    }
+}
+
+impl BehaviorTree {
+    /// Ticks the tree once. `leaf` is called for `Condition` and `Action` nodes and returns
+    /// their status; it must not return `Running` for conditions.
+    pub fn tick(
+        &self,
+        memory: &mut BehaviorMemory,
+        leaf: &mut impl FnMut(&Node) -> NodeStatus,
+    ) -> NodeStatus {
+        let size = self.root.size();
+        if memory.nodes.len() != size {
+            memory.nodes = vec![NodeMemory::default(); size];
+        }
+        tick_node(&self.root, 0, &mut memory.nodes, leaf)
+    }
+}
+
+fn tick_node(
+    node: &Node,
+    index: usize,
+    memory: &mut [NodeMemory],
+    leaf: &mut impl FnMut(&Node) -> NodeStatus,
+) -> NodeStatus {
+    // Pre-order index of every child.
+    let child_indices = || {
+        node.children().iter().scan(index + 1, |next, child| {
+            let child_index = *next;
+            *next += child.size();
+            Some(child_index)
+        })
+    };
+
+    let status = match node {
+        Node::Selector(children) | Node::Sequence(children) => {
+            // A selector moves on after failures, a sequence after successes.
+            let next_on = if matches!(node, Node::Selector(_)) {
+                NodeStatus::Failure
+            } else {
+                NodeStatus::Success
+            };
+            let start = memory[index].cursor;
+            let mut status = next_on;
+            for (i, child_index) in child_indices().enumerate().skip(start) {
+                status = tick_node(&children[i], child_index, memory, leaf);
+                if status == NodeStatus::Running {
+                    memory[index].cursor = i;
+                }
+                if status != next_on {
+                    break;
+                }
+            }
+            status
+        }
+        Node::Parallel {
+            success_threshold,
+            children,
+        } => {
+            let (mut successes, mut failures) = (0, 0);
+            for (child, child_index) in children.iter().zip(child_indices()) {
+                let status = match memory[child_index].finished {
+                    Some(status) => status,
+                    None => tick_node(child, child_index, memory, leaf),
+                };
+                match status {
+                    NodeStatus::Success => successes += 1,
+                    NodeStatus::Failure => failures += 1,
+                    NodeStatus::Running => {}
+                }
+                if status != NodeStatus::Running {
+                    memory[child_index].finished = Some(status);
+                }
+            }
+            if successes >= *success_threshold {
+                NodeStatus::Success
+            } else if children.len() - failures < *success_threshold {
+                NodeStatus::Failure
+            } else {
+                NodeStatus::Running
+            }
+        }
+        Node::Inverter(child) => match tick_node(child, index + 1, memory, leaf) {
+            NodeStatus::Success => NodeStatus::Failure,
+            NodeStatus::Failure => NodeStatus::Success,
+            NodeStatus::Running => NodeStatus::Running,
+        },
+        Node::Repeat { times, child } => {
+            if times.is_some_and(|times| memory[index].cursor >= times as usize) {
+                NodeStatus::Success
+            } else {
+                match tick_node(child, index + 1, memory, leaf) {
+                    NodeStatus::Success => {
+                        memory[index].cursor += 1;
+                        if times.is_some_and(|times| memory[index].cursor >= times as usize) {
+                            NodeStatus::Success
+                        } else {
+                            NodeStatus::Running
+                        }
+                    }
+                    status => status,
+                }
+            }
+        }
+        Node::Condition(_) | Node::Action(_) => leaf(node),
+    };
+
+    // A finished node starts over the next time it's ticked.
+    if status != NodeStatus::Running {
+        memory[index..index + node.size()].fill(NodeMemory::default());
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NodeStatus::{Failure, Running, Success};
+    use super::*;
+    use crate::arena::areas::AreaID;
+
+    /// A leaf told apart by `name`.
+    fn leaf(name: &str) -> Node {
+        Node::Action(Action::MoveToArea(name.into()))
+    }
+
+    /// Ticks `tree` once. Leaves return their status in `statuses`, `Running` if missing.
+    /// Also returns the leaves ticked, in order.
+    fn tick(
+        tree: &Node,
+        memory: &mut BehaviorMemory,
+        statuses: &[(&str, NodeStatus)],
+    ) -> (NodeStatus, Vec<String>) {
+        let tree = BehaviorTree { root: tree.clone() };
+        let mut ticked = Vec::new();
+        let status = tree.tick(memory, &mut |node| {
+            let Node::Action(Action::MoveToArea(AreaID(name))) = node else {
+                unreachable!("tests only use `leaf`");
+            };
+            ticked.push(name.clone());
+            statuses
+                .iter()
+                .find(|(leaf, _)| leaf == name)
+                .map_or(Running, |(_, status)| *status)
+        });
+        (status, ticked)
+    }
+
+    #[test]
+    fn sequence_resumes_at_the_running_child() {
+        let tree = Node::Sequence(vec![leaf("a"), leaf("b"), leaf("c")]);
+        let mut memory = BehaviorMemory::default();
+
+        let (status, ticked) = tick(&tree, &mut memory, &[("a", Success)]);
+        assert_eq!((status, ticked), (Running, vec!["a".into(), "b".into()]));
+        let (status, ticked) = tick(&tree, &mut memory, &[("b", Success), ("c", Success)]);
+        assert_eq!((status, ticked), (Success, vec!["b".into(), "c".into()]));
+        // Finished, so the next tick starts over.
+        let (status, ticked) = tick(&tree, &mut memory, &[("a", Failure)]);
+        assert_eq!((status, ticked), (Failure, vec!["a".into()]));
+    }
+
+    #[test]
+    fn selector_resumes_at_the_running_child() {
+        let tree = Node::Selector(vec![leaf("a"), leaf("b"), leaf("c")]);
+        let mut memory = BehaviorMemory::default();
+
+        let (status, ticked) = tick(&tree, &mut memory, &[("a", Failure)]);
+        assert_eq!((status, ticked), (Running, vec!["a".into(), "b".into()]));
+        let (status, ticked) = tick(&tree, &mut memory, &[("b", Failure), ("c", Success)]);
+        assert_eq!((status, ticked), (Success, vec!["b".into(), "c".into()]));
+        let (status, _) = tick(
+            &tree,
+            &mut memory,
+            &[("a", Failure), ("b", Failure), ("c", Failure)],
+        );
+        assert_eq!(status, Failure);
+    }
+
+    #[test]
+    fn parallel_succeeds_at_the_threshold() {
+        let tree = Node::Parallel {
+            success_threshold: 2,
+            children: vec![leaf("a"), leaf("b"), leaf("c")],
+        };
+        let mut memory = BehaviorMemory::default();
+
+        let (status, _) = tick(&tree, &mut memory, &[("a", Success)]);
+        assert_eq!(status, Running);
+        // Finished children aren't ticked again.
+        let (status, ticked) = tick(&tree, &mut memory, &[("b", Success)]);
+        assert_eq!((status, ticked), (Success, vec!["b".into(), "c".into()]));
+    }
+
+    #[test]
+    fn parallel_fails_once_the_threshold_is_out_of_reach() {
+        let tree = Node::Parallel {
+            success_threshold: 2,
+            children: vec![leaf("a"), leaf("b"), leaf("c")],
+        };
+        let mut memory = BehaviorMemory::default();
+
+        let (status, _) = tick(&tree, &mut memory, &[("a", Failure)]);
+        assert_eq!(status, Running);
+        let (status, _) = tick(&tree, &mut memory, &[("b", Failure)]);
+        assert_eq!(status, Failure);
+
+        // More than there are children can never succeed.
+        let tree = Node::Parallel {
+            success_threshold: 3,
+            children: vec![leaf("a"), leaf("b")],
+        };
+        let (status, _) = tick(&tree, &mut BehaviorMemory::default(), &[]);
+        assert_eq!(status, Failure);
+    }
+
+    #[test]
+    fn repeat_runs_the_child_until_done() {
+        let tree = Node::Repeat {
+            times: Some(3),
+            child: Box::new(leaf("a")),
+        };
+        let mut memory = BehaviorMemory::default();
+
+        for _ in 0..2 {
+            let (status, _) = tick(&tree, &mut memory, &[("a", Success)]);
+            assert_eq!(status, Running);
+        }
+        let (status, _) = tick(&tree, &mut memory, &[("a", Success)]);
+        assert_eq!(status, Success);
+
+        let (status, _) = tick(&tree, &mut memory, &[("a", Success)]);
+        assert_eq!(status, Running);
+        let (status, _) = tick(&tree, &mut memory, &[("a", Failure)]);
+        assert_eq!(status, Failure);
+    }
+
+    #[test]
+    fn inverter_swaps_success_and_failure() {
+        let tree = Node::Inverter(Box::new(leaf("a")));
+        let mut memory = BehaviorMemory::default();
+        for (child, expected) in [(Success, Failure), (Failure, Success), (Running, Running)] {
+            let (status, _) = tick(&tree, &mut memory, &[("a", child)]);
+            assert_eq!(status, expected);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Per-AI memory that outlives a tick, so rules can react to what happened before.
+//!
+//! Rules write flags and counters with `Action::SetFlag` and `Action::IncrementCounter` and
+//! read them with `Condition::FlagSet` and `Condition::CounterAtLeast`. A rule whose action
+//! only writes the blackboard applies it once each time it gets selected. The game keeps a few
+//! entries up to date itself, named by the constants below.
+
+use super::AiPlayer;
+use crate::arena::ArenaConfig;
+use crate::building::StructureType;
+use crate::player::PlayerStatus;
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+
+/// Counter of obstacles the AI built.
+pub const OBSTACLES_BUILT: &str = "obstacles_built";
+/// Counter of turrets the AI built.
+pub const TURRETS_BUILT: &str = "turrets_built";
+/// Position of the tile the nearest enemy was last seen on.
+pub const LAST_SEEN_ENEMY: &str = "last_seen_enemy";
+
+#[derive(Component, Default, Debug)]
+pub struct Blackboard {
+    pub flags: HashSet<String>,
+    pub counters: HashMap<String, u32>,
+    /// Remembered tiles, by name.
+    pub positions: HashMap<String, (u32, u32)>,
+}
+
+impl Blackboard {
+    pub fn flag(&self, flag: &str) -> bool {
+        self.flags.contains(flag)
+    }
+
+    pub fn set_flag(&mut self, flag: &str, value: bool) {
+        if value {
+            self.flags.insert(flag.to_string());
+        } else {
+            self.flags.remove(flag);
+        }
+    }
+
+    /// 0 for counters never incremented.
+    pub fn counter(&self, counter: &str) -> u32 {
+        self.counters.get(counter).copied().unwrap_or(0)
+    }
+
+    pub fn increment(&mut self, counter: &str) {
+        *self.counters.entry(counter.to_string()).or_insert(0) += 1;
+    }
+
+    /// Counts a structure built by the AI.
+    pub fn record_built(&mut self, structure: StructureType) {
+        match structure {
+            StructureType::Obstacle => self.increment(OBSTACLES_BUILT),
+            StructureType::Turret => self.increment(TURRETS_BUILT),
+            _ => {}
+        }
+    }
+}
+
+/// Remembers where enemies were seen, before the rules run.
+pub fn update_blackboards(
+    mut query: Query<(&PlayerStatus, &mut Blackboard), With<AiPlayer>>,
+    config: Res<ArenaConfig>,
+) {
+    for (status, mut blackboard) in query.iter_mut() {
+        if let Some(enemy_pos) = status.nearest_enemy_position {
+            let tile = config.world_to_tile(enemy_pos);
+            blackboard
+                .positions
+                .insert(LAST_SEEN_ENEMY.to_string(), tile);
+        }
+    }
+}
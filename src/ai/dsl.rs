@@ -0,0 +1,1180 @@
+//! Text syntax for rule sets, one rule per line:
+//!
+//! ```text
+//! # priority name: condition [until C] -> action [hold S] [cooldown S] [score SCORE]
+//! 95 DeployCombatTurret: enemy_visible and has turret>=1 -> build turret
+//! 80 EngageEnemy: enemy_within 5 until not enemy_visible -> chase hold 2.0
+//! 20 ClaimCenter: not in_area CenterArena -> move_to CenterArena cooldown 1.5
+//! ```
+//!
+//! `until`, `hold` and `cooldown` set `Rule::exit_condition`, `min_duration` and `cooldown`.
+//! A `strategy utility` line before the first rule selects `Strategy::Utility`.
+//!
+//! Conditions:
+//!
+//! ```text
+//! true | enemy_visible | under_attack | health_low N | in_area AREA
+//! has ITEM >= N | has ITEM <= N | has ITEM == N
+//! enemy_within N | distance AREA < N | enemy_in_area AREA | area_visible AREA
+//! structure_nearby STRUCTURE N | flag NAME | counter NAME >= N
+//! not C | C and C ... | C or C ... | all(C, ...) | any(C, ...) | (C)
+//! ```
+//!
+//! Actions:
+//!
+//! ```text
+//! move_to AREA | chase | flee | build STRUCTURE [facing DIRECTION] [at PLACEMENT] | idle
+//! set_flag NAME | clear_flag NAME | increment NAME
+//! collect ITEM | patrol(AREA, ...) | guard AREA N | destroy X Y | move_to_tile X Y
+//! sequence(ACTION, ...)
+//! ```
+//!
+//! `PLACEMENT` is `here`, the default, `choke_point`, `coverage` or `auto`.
+//!
+//! Scores:
+//!
+//! ```text
+//! NUMBER | enemy_distance | hp_ratio | item_count ITEM | visible_enemies
+//! S + S ... | S * S ... | sum(S, ...) | product(S, ...) | min(S, ...) | max(S, ...)
+//! if(C, S, S) | (S)
+//! ```
+//!
+//! `not` binds tighter than `and`, which binds tighter than `or`, and `*` tighter than `+`.
+//! `all`, `any`, `sum` and `product` are `and`, `or`, `+` and `*` with fewer than two
+//! operands. Names and areas that aren't identifiers are
+//! written as quoted strings, and a line break inside parentheses continues the rule.
+//! `print` writes any `RuleSet` so that `parse` returns it unchanged, as long as its numbers
+//! are finite, which `RuleSet::validate` checks.
+
+use super::rules::{
+    Action, Comparison, Condition, Placement, Rule, RuleSet, Score, Strategy, ITEMS,
+};
+use crate::arena::areas::AreaID;
+use crate::arena::CollectibleType;
+use crate::building::StructureType;
+use crate::combat::TurretDirection;
+use std::fmt::{self, Write as _};
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+const STRUCTURES: &[(StructureType, &str)] = &[
+    (StructureType::Wall, "wall"),
+    (StructureType::Obstacle, "obstacle"),
+    (StructureType::Turret, "turret"),
+];
+
+const STRATEGIES: &[(Strategy, &str)] = &[
+    (Strategy::Priority, "priority"),
+    (Strategy::Utility, "utility"),
+];
+
+const DIRECTIONS: &[(TurretDirection, &str)] = &[
+    (TurretDirection::North, "north"),
+    (TurretDirection::East, "east"),
+    (TurretDirection::South, "south"),
+    (TurretDirection::West, "west"),
+];
+
+const PLACEMENTS: &[(Placement, &str)] = &[
+    (Placement::Here, "here"),
+    (Placement::ChokePoint, "choke_point"),
+    (Placement::Coverage, "coverage"),
+    (Placement::Auto, "auto"),
+];
+
+/// File extension of rule sets in the text syntax, see `load`.
+pub const EXTENSION: &str = "rules";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// 1-based, like the column.
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse(text: &str) -> Result<RuleSet, ParseError> {
+    let mut parser = Parser::new(text)?;
+    let mut rules = Vec::new();
+    let mut strategy = None;
+    loop {
+        while parser.eat(&Token::Newline) {}
+        if parser.peek().is_none() {
+            break;
+        }
+        let pos = parser.pos;
+        if parser.eat_keyword("strategy") {
+            if strategy.is_some() || !rules.is_empty() {
+                return Err(parser.error_at(
+                    pos,
+                    "`strategy` must come once, before the first rule".to_string(),
+                ));
+            }
+            strategy = Some(parser.one_of("strategy", STRATEGIES)?);
+            if parser.peek().is_some() {
+                parser.expect(&Token::Newline)?;
+            }
+            continue;
+        }
+        rules.push(parser.rule()?);
+    }
+    Ok(RuleSet {
+        rules,
+        strategy: strategy.unwrap_or_default(),
+    })
+}
+
+pub fn print(rule_set: &RuleSet) -> String {
+    let mut text = String::new();
+    if rule_set.strategy != Strategy::default() {
+        let _ = writeln!(text, "strategy {}", lookup(STRATEGIES, &rule_set.strategy));
+    }
+    for rule in &rule_set.rules {
+        let _ = write!(text, "{} ", rule.priority);
+        write_name(&mut text, &rule.name);
+        text.push_str(": ");
+        write_condition(&mut text, &rule.condition);
+        if let Some(exit_condition) = &rule.exit_condition {
+            text.push_str(" until ");
+            write_condition(&mut text, exit_condition);
+        }
+        text.push_str(" -> ");
+        write_action(&mut text, &rule.action);
+        if let Some(min_duration) = rule.min_duration {
+            text.push_str(" hold ");
+            write_decimal(&mut text, min_duration);
+        }
+        if let Some(cooldown) = rule.cooldown {
+            text.push_str(" cooldown ");
+            write_decimal(&mut text, cooldown);
+        }
+        if let Some(score) = &rule.score {
+            text.push_str(" score ");
+            write_score(&mut text, score);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Loads a rule set file, in the text syntax if it ends in `.rules` and as JSON otherwise.
+pub fn load(path: impl AsRef<Path>) -> Result<RuleSet, String> {
+    let path = path.as_ref();
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if path
+        .extension()
+        .is_some_and(|extension| extension == EXTENSION)
+    {
+        parse(&contents).map_err(|e| format!("{}:{}", path.display(), e))
+    } else {
+        serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// Lexer
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    /// Kept as written, so it parses to exactly the `f32` it was printed from.
+    Decimal(String),
+    Colon,
+    Comma,
+    Arrow,
+    LParen,
+    RParen,
+    Compare(Comparison),
+    Less,
+    Plus,
+    Star,
+    /// End of a rule. Not emitted inside parentheses or for blank lines.
+    Newline,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "`{}`", ident),
+            Token::Str(string) => write!(f, "{:?}", string),
+            Token::Int(value) => write!(f, "`{}`", value),
+            Token::Decimal(text) => write!(f, "`{}`", text),
+            Token::Colon => write!(f, "`:`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Arrow => write!(f, "`->`"),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::Compare(op) => write!(f, "`{}`", op),
+            Token::Less => write!(f, "`<`"),
+            Token::Plus => write!(f, "`+`"),
+            Token::Star => write!(f, "`*`"),
+            Token::Newline => write!(f, "end of line"),
+        }
+    }
+}
+
+struct Spanned {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer<'_> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn bump_if(&mut self, expected: char) -> bool {
+        let matches = self.chars.peek() == Some(&expected);
+        if matches {
+            self.bump();
+        }
+        matches
+    }
+
+    /// An integer, or a decimal number if it has a fractional part.
+    fn number(&mut self, sign: &str) -> Result<Token, String> {
+        let mut text = sign.to_string() + &self.take_while(|c| c.is_ascii_digit());
+        if self.bump_if('.') {
+            text.push('.');
+            text += &self.take_while(|c| c.is_ascii_digit());
+            return Ok(Token::Decimal(text));
+        }
+        text.parse()
+            .map(Token::Int)
+            .map_err(|_| format!("number `{}` is too large", text))
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(&c) = self.chars.peek().filter(|&&c| f(c)) {
+            taken.push(c);
+            self.bump();
+        }
+        taken
+    }
+}
+
+fn tokenize(text: &str) -> Result<(Vec<Spanned>, (usize, usize)), ParseError> {
+    let mut lexer = Lexer {
+        chars: text.chars().peekable(),
+        line: 1,
+        column: 1,
+    };
+    let mut tokens: Vec<Spanned> = Vec::new();
+    let mut depth = 0usize;
+
+    while let Some(&c) = lexer.chars.peek() {
+        let (line, column) = (lexer.line, lexer.column);
+        let error = |message: String| ParseError {
+            line,
+            column,
+            message,
+        };
+
+        let token = match c {
+            '\n' => {
+                lexer.bump();
+                let after_rule = tokens
+                    .last()
+                    .is_some_and(|last| last.token != Token::Newline);
+                if depth > 0 || !after_rule {
+                    continue;
+                }
+                Token::Newline
+            }
+            '#' => {
+                lexer.take_while(|c| c != '\n');
+                continue;
+            }
+            c if c.is_whitespace() => {
+                lexer.bump();
+                continue;
+            }
+            '(' | ')' | ':' | ',' | '+' | '*' => {
+                lexer.bump();
+                match c {
+                    '(' => {
+                        depth += 1;
+                        Token::LParen
+                    }
+                    ')' => {
+                        depth = depth.saturating_sub(1);
+                        Token::RParen
+                    }
+                    ':' => Token::Colon,
+                    '+' => Token::Plus,
+                    '*' => Token::Star,
+                    _ => Token::Comma,
+                }
+            }
+            '-' => {
+                lexer.bump();
+                if lexer.bump_if('>') {
+                    Token::Arrow
+                } else if lexer.chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    lexer.number("-").map_err(error)?
+                } else {
+                    return Err(error("expected `->` or a number after `-`".to_string()));
+                }
+            }
+            '>' | '<' | '=' => {
+                lexer.bump();
+                match (c, lexer.bump_if('=')) {
+                    ('>', true) => Token::Compare(Comparison::AtLeast),
+                    ('<', true) => Token::Compare(Comparison::AtMost),
+                    ('=', true) => Token::Compare(Comparison::Equal),
+                    ('<', false) => Token::Less,
+                    _ => return Err(error(format!("expected `{}=`", c))),
+                }
+            }
+            '"' => {
+                lexer.bump();
+                let mut string = String::new();
+                loop {
+                    match lexer.bump() {
+                        None | Some('\n') => {
+                            return Err(error("unterminated string".to_string()));
+                        }
+                        Some('"') => break,
+                        Some('\\') => match lexer.bump() {
+                            Some('n') => string.push('\n'),
+                            Some(c @ ('"' | '\\')) => string.push(c),
+                            _ => {
+                                return Err(ParseError {
+                                    line: lexer.line,
+                                    column: lexer.column - 1,
+                                    message: "unknown escape, expected \\n, \\\" or \\\\"
+                                        .to_string(),
+                                });
+                            }
+                        },
+                        Some(c) => string.push(c),
+                    }
+                }
+                Token::Str(string)
+            }
+            c if c.is_ascii_digit() => lexer.number("").map_err(error)?,
+            c if is_ident_start(c) => Token::Ident(lexer.take_while(is_ident_char)),
+            c => return Err(error(format!("unexpected character `{}`", c))),
+        };
+        tokens.push(Spanned {
+            token,
+            line,
+            column,
+        });
+    }
+
+    Ok((tokens, (lexer.line, lexer.column)))
+}
+
+// Parser
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+    /// Position after the last character, for errors at the end of the text.
+    end: (usize, usize),
+}
+
+impl Parser {
+    fn new(text: &str) -> Result<Self, ParseError> {
+        let (tokens, end) = tokenize(text)?;
+        Ok(Self {
+            tokens,
+            pos: 0,
+            end,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|spanned| &spanned.token)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matches = self.peek() == Some(token);
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let matches = matches!(self.peek(), Some(Token::Ident(ident)) if ident == keyword);
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    /// Error at the token at `pos`, or at the end of the text.
+    fn error_at(&self, pos: usize, message: String) -> ParseError {
+        let (line, column) = self
+            .tokens
+            .get(pos)
+            .map_or(self.end, |spanned| (spanned.line, spanned.column));
+        ParseError {
+            line,
+            column,
+            message,
+        }
+    }
+
+    /// "expected X, found Y" at the current token.
+    fn expected(&self, what: &str) -> ParseError {
+        let found = match self.peek() {
+            Some(token) => token.to_string(),
+            None => "end of file".to_string(),
+        };
+        self.error_at(self.pos, format!("expected {}, found {}", what, found))
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.expected(&token.to_string()))
+        }
+    }
+
+    fn int(&mut self, what: &str) -> Result<i64, ParseError> {
+        match self.peek() {
+            Some(&Token::Int(value)) => {
+                self.pos += 1;
+                Ok(value)
+            }
+            _ => Err(self.expected(what)),
+        }
+    }
+
+    fn count(&mut self) -> Result<u32, ParseError> {
+        let pos = self.pos;
+        let value = self.int("a number")?;
+        u32::try_from(value).map_err(|_| {
+            self.error_at(
+                pos,
+                format!(
+                    "expected a number from 0 to {}, found `{}`",
+                    u32::MAX,
+                    value
+                ),
+            )
+        })
+    }
+
+    fn decimal(&mut self, what: &str) -> Result<f32, ParseError> {
+        let value: f32 = match self.peek() {
+            Some(&Token::Int(value)) => value as f32,
+            Some(Token::Decimal(text)) => text.parse().expect("decimal tokens are valid numbers"),
+            _ => return Err(self.expected(what)),
+        };
+        if !value.is_finite() {
+            let found = &self.tokens[self.pos].token;
+            return Err(self.error_at(self.pos, format!("number {} is too large", found)));
+        }
+        self.pos += 1;
+        Ok(value)
+    }
+
+    /// An identifier or quoted string.
+    fn name(&mut self, what: &str) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Ident(name) | Token::Str(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.expected(what)),
+        }
+    }
+
+    fn area(&mut self) -> Result<AreaID, ParseError> {
+        self.name("an area").map(AreaID)
+    }
+
+    /// One of `names`, ignoring case.
+    fn one_of<T: Copy>(&mut self, what: &str, names: &[(T, &str)]) -> Result<T, ParseError> {
+        let pos = self.pos;
+        let name = match self.peek() {
+            Some(Token::Ident(name)) => name.clone(),
+            _ => return Err(self.expected(what)),
+        };
+        self.pos += 1;
+        names
+            .iter()
+            .find(|(_, candidate)| candidate.eq_ignore_ascii_case(&name))
+            .map(|(value, _)| *value)
+            .ok_or_else(|| {
+                let expected: Vec<String> = names
+                    .iter()
+                    .map(|(_, candidate)| candidate.to_lowercase())
+                    .collect();
+                self.error_at(
+                    pos,
+                    format!(
+                        "unknown {} `{}`, expected one of: {}",
+                        what,
+                        name,
+                        expected.join(", ")
+                    ),
+                )
+            })
+    }
+
+    fn rule(&mut self) -> Result<Rule, ParseError> {
+        let pos = self.pos;
+        let priority = self.int("a priority")?;
+        let priority = i32::try_from(priority)
+            .map_err(|_| self.error_at(pos, format!("priority `{}` is out of range", priority)))?;
+        let name = self.name("a rule name")?;
+        self.expect(&Token::Colon)?;
+        let condition = self.or_condition()?;
+        let exit_condition = if self.eat_keyword("until") {
+            Some(self.or_condition()?)
+        } else {
+            None
+        };
+        self.expect(&Token::Arrow)?;
+        let action = self.action()?;
+        let min_duration = if self.eat_keyword("hold") {
+            Some(self.decimal("a number of seconds")?)
+        } else {
+            None
+        };
+        let cooldown = if self.eat_keyword("cooldown") {
+            Some(self.decimal("a number of seconds")?)
+        } else {
+            None
+        };
+        let score = if self.eat_keyword("score") {
+            Some(self.sum_score()?)
+        } else {
+            None
+        };
+        if self.peek().is_some() {
+            self.expect(&Token::Newline)?;
+        }
+        Ok(Rule {
+            name,
+            priority,
+            condition,
+            action,
+            exit_condition,
+            min_duration,
+            cooldown,
+            score,
+        })
+    }
+
+    fn or_condition(&mut self) -> Result<Condition, ParseError> {
+        let first = self.and_condition()?;
+        if !self.eat_keyword("or") {
+            return Ok(first);
+        }
+        let mut conditions = vec![first, self.and_condition()?];
+        while self.eat_keyword("or") {
+            conditions.push(self.and_condition()?);
+        }
+        Ok(Condition::Or(conditions))
+    }
+
+    fn and_condition(&mut self) -> Result<Condition, ParseError> {
+        let first = self.not_condition()?;
+        if !self.eat_keyword("and") {
+            return Ok(first);
+        }
+        let mut conditions = vec![first, self.not_condition()?];
+        while self.eat_keyword("and") {
+            conditions.push(self.not_condition()?);
+        }
+        Ok(Condition::And(conditions))
+    }
+
+    fn not_condition(&mut self) -> Result<Condition, ParseError> {
+        if self.eat_keyword("not") {
+            Ok(Condition::Not(Box::new(self.not_condition()?)))
+        } else {
+            self.primary_condition()
+        }
+    }
+
+    fn primary_condition(&mut self) -> Result<Condition, ParseError> {
+        if self.eat(&Token::LParen) {
+            let condition = self.or_condition()?;
+            self.expect(&Token::RParen)?;
+            return Ok(condition);
+        }
+
+        let pos = self.pos;
+        let keyword = match self.peek() {
+            Some(Token::Ident(keyword)) => keyword.clone(),
+            _ => return Err(self.expected("a condition")),
+        };
+        self.pos += 1;
+
+        Ok(match keyword.as_str() {
+            "true" => Condition::True,
+            "enemy_visible" => Condition::IsEnemyVisible,
+            "under_attack" => Condition::IsUnderAttack,
+            "health_low" => Condition::IsHealthLow {
+                threshold: self.count()?,
+            },
+            "in_area" => Condition::InArea(self.area()?),
+            "has" => {
+                let item = self.item()?;
+                let op = match self.bump() {
+                    Some(Token::Compare(op)) => op,
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.expected("`>=`, `<=` or `==`"));
+                    }
+                };
+                Condition::HasItem {
+                    item,
+                    op,
+                    count: self.count()?,
+                }
+            }
+            "enemy_within" => Condition::EnemyWithinTiles(self.count()?),
+            "distance" => {
+                let area = self.area()?;
+                self.expect(&Token::Less)?;
+                Condition::AreaDistanceBelow {
+                    area,
+                    tiles: self.count()?,
+                }
+            }
+            "enemy_in_area" => Condition::EnemyInArea(self.area()?),
+            "area_visible" => Condition::AreaVisible(self.area()?),
+            "structure_nearby" => Condition::StructureNearby {
+                ty: self.one_of("structure", STRUCTURES)?,
+                radius: self.count()?,
+            },
+            "flag" => Condition::FlagSet(self.name("a flag")?),
+            "counter" => {
+                let counter = self.name("a counter")?;
+                self.expect(&Token::Compare(Comparison::AtLeast))?;
+                Condition::CounterAtLeast {
+                    counter,
+                    value: self.count()?,
+                }
+            }
+            "all" => Condition::And(self.list(Self::or_condition)?),
+            "any" => Condition::Or(self.list(Self::or_condition)?),
+            _ => {
+                return Err(self.error_at(pos, format!("unknown condition `{}`", keyword)));
+            }
+        })
+    }
+
+    fn item(&mut self) -> Result<CollectibleType, ParseError> {
+        self.one_of("item", ITEMS)
+    }
+
+    /// `(X, X, ...)`, possibly empty, with `item` parsing each `X`.
+    fn list<T>(
+        &mut self,
+        item: fn(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        self.expect(&Token::LParen)?;
+        let mut items = Vec::new();
+        if self.eat(&Token::RParen) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(&Token::RParen) {
+                return Ok(items);
+            }
+            if !self.eat(&Token::Comma) {
+                return Err(self.expected("`,` or `)`"));
+            }
+        }
+    }
+
+    fn sum_score(&mut self) -> Result<Score, ParseError> {
+        let first = self.product_score()?;
+        if !self.eat(&Token::Plus) {
+            return Ok(first);
+        }
+        let mut scores = vec![first, self.product_score()?];
+        while self.eat(&Token::Plus) {
+            scores.push(self.product_score()?);
+        }
+        Ok(Score::Add(scores))
+    }
+
+    fn product_score(&mut self) -> Result<Score, ParseError> {
+        let first = self.primary_score()?;
+        if !self.eat(&Token::Star) {
+            return Ok(first);
+        }
+        let mut scores = vec![first, self.primary_score()?];
+        while self.eat(&Token::Star) {
+            scores.push(self.primary_score()?);
+        }
+        Ok(Score::Mul(scores))
+    }
+
+    fn primary_score(&mut self) -> Result<Score, ParseError> {
+        if self.eat(&Token::LParen) {
+            let score = self.sum_score()?;
+            self.expect(&Token::RParen)?;
+            return Ok(score);
+        }
+        if matches!(self.peek(), Some(Token::Int(_) | Token::Decimal(_))) {
+            return Ok(Score::Constant(self.decimal("a number")?));
+        }
+
+        let pos = self.pos;
+        let keyword = match self.peek() {
+            Some(Token::Ident(keyword)) => keyword.clone(),
+            _ => return Err(self.expected("a score")),
+        };
+        self.pos += 1;
+
+        Ok(match keyword.as_str() {
+            "enemy_distance" => Score::EnemyDistance,
+            "hp_ratio" => Score::HpRatio,
+            "item_count" => Score::ItemCount { item: self.item()? },
+            "visible_enemies" => Score::VisibleEnemies,
+            "sum" => Score::Add(self.list(Self::sum_score)?),
+            "product" => Score::Mul(self.list(Self::sum_score)?),
+            "min" => Score::Min(self.list(Self::sum_score)?),
+            "max" => Score::Max(self.list(Self::sum_score)?),
+            "if" => {
+                self.expect(&Token::LParen)?;
+                let condition = self.or_condition()?;
+                self.expect(&Token::Comma)?;
+                let then = self.sum_score()?;
+                self.expect(&Token::Comma)?;
+                let otherwise = self.sum_score()?;
+                self.expect(&Token::RParen)?;
+                Score::If {
+                    condition,
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                }
+            }
+            _ => return Err(self.error_at(pos, format!("unknown score `{}`", keyword))),
+        })
+    }
+
+    /// `(S, S, ...)`, possibly empty.
+    fn action(&mut self) -> Result<Action, ParseError> {
+        let pos = self.pos;
+        let keyword = match self.peek() {
+            Some(Token::Ident(keyword)) => keyword.clone(),
+            _ => return Err(self.expected("an action")),
+        };
+        self.pos += 1;
+
+        Ok(match keyword.as_str() {
+            "move_to" => Action::MoveToArea(self.area()?),
+            "chase" => Action::ChaseEnemy,
+            "flee" => Action::Flee,
+            "build" => {
+                let structure = self.one_of("structure", STRUCTURES)?;
+                let direction = if self.eat_keyword("facing") {
+                    Some(self.one_of("direction", DIRECTIONS)?)
+                } else {
+                    None
+                };
+                let placement = if self.eat_keyword("at") {
+                    self.one_of("placement", PLACEMENTS)?
+                } else {
+                    Placement::Here
+                };
+                Action::Build {
+                    structure,
+                    direction,
+                    placement,
+                }
+            }
+            "idle" => Action::Idle,
+            "set_flag" | "clear_flag" => Action::SetFlag {
+                flag: self.name("a flag")?,
+                value: keyword == "set_flag",
+            },
+            "increment" => Action::IncrementCounter(self.name("a counter")?),
+            "collect" => Action::CollectNearest(self.item()?),
+            "patrol" => Action::PatrolAreas(self.list(Self::area)?),
+            "guard" => Action::GuardArea {
+                area: self.area()?,
+                radius: self.count()?,
+            },
+            "destroy" => Action::DestroyStructureAt {
+                x: self.count()?,
+                y: self.count()?,
+            },
+            "move_to_tile" => Action::MoveToTile {
+                x: self.count()?,
+                y: self.count()?,
+            },
+            "sequence" => Action::Sequence(self.list(Self::action)?),
+            _ => return Err(self.error_at(pos, format!("unknown action `{}`", keyword))),
+        })
+    }
+}
+
+// Printer
+
+fn write_name(out: &mut String, name: &str) {
+    let mut chars = name.chars();
+    if chars.next().is_some_and(is_ident_start) && chars.all(is_ident_char) {
+        out.push_str(name);
+        return;
+    }
+    out.push('"');
+    for c in name.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Always with a decimal point, so large values don't lex as too large integers.
+fn write_decimal(out: &mut String, seconds: f32) {
+    let text = seconds.to_string();
+    out.push_str(&text);
+    if !text.contains('.') {
+        out.push_str(".0");
+    }
+}
+
+fn lookup<T: PartialEq>(names: &[(T, &'static str)], value: &T) -> &'static str {
+    names
+        .iter()
+        .find(|(candidate, _)| candidate == value)
+        .map(|(_, name)| *name)
+        .expect("every variant has a name")
+}
+
+fn write_condition(out: &mut String, condition: &Condition) {
+    match condition {
+        Condition::True => out.push_str("true"),
+        Condition::IsEnemyVisible => out.push_str("enemy_visible"),
+        Condition::IsUnderAttack => out.push_str("under_attack"),
+        Condition::IsHealthLow { threshold } => {
+            let _ = write!(out, "health_low {}", threshold);
+        }
+        Condition::InArea(area) => {
+            out.push_str("in_area ");
+            write_name(out, &area.0);
+        }
+        Condition::HasItem { item, op, count } => {
+            let item = lookup(ITEMS, item).to_lowercase();
+            let _ = write!(out, "has {}{}{}", item, op, count);
+        }
+        Condition::EnemyWithinTiles(tiles) => {
+            let _ = write!(out, "enemy_within {}", tiles);
+        }
+        Condition::AreaDistanceBelow { area, tiles } => {
+            out.push_str("distance ");
+            write_name(out, &area.0);
+            let _ = write!(out, " < {}", tiles);
+        }
+        Condition::EnemyInArea(area) => {
+            out.push_str("enemy_in_area ");
+            write_name(out, &area.0);
+        }
+        Condition::AreaVisible(area) => {
+            out.push_str("area_visible ");
+            write_name(out, &area.0);
+        }
+        Condition::StructureNearby { ty, radius } => {
+            let _ = write!(
+                out,
+                "structure_nearby {} {}",
+                lookup(STRUCTURES, ty),
+                radius
+            );
+        }
+        Condition::FlagSet(flag) => {
+            out.push_str("flag ");
+            write_name(out, flag);
+        }
+        Condition::CounterAtLeast { counter, value } => {
+            out.push_str("counter ");
+            write_name(out, counter);
+            let _ = write!(out, " >= {}", value);
+        }
+        Condition::And(conditions) | Condition::Or(conditions) => {
+            let (infix, function) = match condition {
+                Condition::And(_) => (" and ", "all"),
+                _ => (" or ", "any"),
+            };
+            if conditions.len() < 2 {
+                out.push_str(function);
+                out.push('(');
+                if let Some(condition) = conditions.first() {
+                    write_condition(out, condition);
+                }
+                out.push(')');
+                return;
+            }
+            for (i, condition) in conditions.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(infix);
+                }
+                write_operand(out, condition);
+            }
+        }
+        Condition::Not(condition) => {
+            out.push_str("not ");
+            write_operand(out, condition);
+        }
+    }
+}
+
+/// Parenthesizes infix `and`/`or`, so nesting survives the round trip.
+fn write_operand(out: &mut String, condition: &Condition) {
+    match condition {
+        Condition::And(conditions) | Condition::Or(conditions) if conditions.len() >= 2 => {
+            out.push('(');
+            write_condition(out, condition);
+            out.push(')');
+        }
+        _ => write_condition(out, condition),
+    }
+}
+
+fn write_score(out: &mut String, score: &Score) {
+    let write_list = |out: &mut String, function: &str, scores: &[Score]| {
+        out.push_str(function);
+        out.push('(');
+        for (i, score) in scores.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            write_score(out, score);
+        }
+        out.push(')');
+    };
+
+    match score {
+        Score::Constant(value) => write_decimal(out, *value),
+        Score::EnemyDistance => out.push_str("enemy_distance"),
+        Score::HpRatio => out.push_str("hp_ratio"),
+        Score::ItemCount { item } => {
+            let _ = write!(out, "item_count {}", lookup(ITEMS, item).to_lowercase());
+        }
+        Score::VisibleEnemies => out.push_str("visible_enemies"),
+        Score::Add(scores) if scores.len() < 2 => write_list(out, "sum", scores),
+        Score::Mul(scores) if scores.len() < 2 => write_list(out, "product", scores),
+        Score::Add(scores) | Score::Mul(scores) => {
+            let is_add = matches!(score, Score::Add(_));
+            for (i, operand) in scores.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(if is_add { " + " } else { " * " });
+                }
+                // Sums bind looser than products, so only products can hold them bare.
+                let needs_parens = match operand {
+                    Score::Add(scores) => scores.len() >= 2,
+                    Score::Mul(scores) => scores.len() >= 2 && !is_add,
+                    _ => false,
+                };
+                if needs_parens {
+                    out.push('(');
+                    write_score(out, operand);
+                    out.push(')');
+                } else {
+                    write_score(out, operand);
+                }
+            }
+        }
+        Score::Min(scores) => write_list(out, "min", scores),
+        Score::Max(scores) => write_list(out, "max", scores),
+        Score::If {
+            condition,
+            then,
+            otherwise,
+        } => {
+            out.push_str("if(");
+            write_condition(out, condition);
+            out.push_str(", ");
+            write_score(out, then);
+            out.push_str(", ");
+            write_score(out, otherwise);
+            out.push(')');
+        }
+    }
+}
+
+fn write_action(out: &mut String, action: &Action) {
+    match action {
+        Action::MoveToArea(area) => {
+            out.push_str("move_to ");
+            write_name(out, &area.0);
+        }
+        Action::ChaseEnemy => out.push_str("chase"),
+        Action::Flee => out.push_str("flee"),
+        Action::Build {
+            structure,
+            direction,
+            placement,
+        } => {
+            let _ = write!(out, "build {}", lookup(STRUCTURES, structure));
+            if let Some(direction) = direction {
+                let _ = write!(out, " facing {}", lookup(DIRECTIONS, direction));
+            }
+            if *placement != Placement::Here {
+                let _ = write!(out, " at {}", lookup(PLACEMENTS, placement));
+            }
+        }
+        Action::Idle => out.push_str("idle"),
+        Action::SetFlag { flag, value } => {
+            out.push_str(if *value { "set_flag " } else { "clear_flag " });
+            write_name(out, flag);
+        }
+        Action::IncrementCounter(counter) => {
+            out.push_str("increment ");
+            write_name(out, counter);
+        }
+        Action::CollectNearest(item) => {
+            let _ = write!(out, "collect {}", lookup(ITEMS, item).to_lowercase());
+        }
+        Action::PatrolAreas(areas) => {
+            out.push_str("patrol(");
+            for (i, area) in areas.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_name(out, &area.0);
+            }
+            out.push(')');
+        }
+        Action::GuardArea { area, radius } => {
+            out.push_str("guard ");
+            write_name(out, &area.0);
+            let _ = write!(out, " {}", radius);
+        }
+        Action::DestroyStructureAt { x, y } => {
+            let _ = write!(out, "destroy {} {}", x, y);
+        }
+        Action::MoveToTile { x, y } => {
+            let _ = write!(out, "move_to_tile {} {}", x, y);
+        }
+        Action::Sequence(steps) => {
+            out.push_str("sequence(");
+            for (i, step) in steps.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_action(out, step);
+            }
+            out.push(')');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(rule_set: &RuleSet) {
+        let text = print(rule_set);
+        let parsed = parse(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        assert_eq!(&parsed, rule_set, "{}", text);
+    }
+
+    fn error(text: &str) -> (usize, usize, String) {
+        let error = parse(text).expect_err("text is invalid");
+        (error.line, error.column, error.message)
+    }
+
+    #[test]
+    fn builtin_rule_sets_round_trip() {
+        round_trip(&RuleSet::default());
+        round_trip(&RuleSet::new_turret_only());
+    }
+
+    #[test]
+    fn shipped_rule_sets_round_trip() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/ai");
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(dir).expect("assets/ai exists") {
+            let path = entry.expect("readable directory").path();
+            round_trip(&load(&path).unwrap_or_else(|e| panic!("{}", e)));
+            loaded += 1;
+        }
+        assert!(loaded > 0);
+    }
+
+    #[test]
+    fn every_syntax_round_trips() {
+        let text = r#"strategy utility
+10 "Odd \"name\"": not (enemy_visible or under_attack) and all(flag f) until any() -> build turret facing east at coverage hold 0.25 cooldown 1.5
+5 S: has turret<=2 or distance A < 3 or health_low 3 -> move_to "B C" score if(counter n >= 2, (1.0 + hp_ratio) * item_count obstacle, -0.5) + max()
+2 Q: true -> sequence(set_flag f, clear_flag "a b", increment c, patrol(A, "B C"))
+1 G: true -> sequence(collect turret, guard A 4, destroy 3 7, move_to_tile 12 0)
+"#;
+        round_trip(&parse(text).expect("valid text"));
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(
+            error("95 Foo: enemy_visible -> jump"),
+            (1, 26, "unknown action `jump`".to_string())
+        );
+        let (line, column, message) = error("10 A: true -> idle\n20 B: has gold >= 1 -> idle");
+        assert_eq!((line, column), (2, 11));
+        assert!(message.starts_with("unknown item `gold`"), "{}", message);
+        assert_eq!(
+            error("10 A: true ->"),
+            (1, 14, "expected an action, found end of file".to_string())
+        );
+        assert_eq!(error("10 \"A: true -> idle").1, 4);
+    }
+
+    #[test]
+    fn non_finite_numbers_are_rejected() {
+        let huge = format!("10 A: true -> idle hold 1{}.0", "0".repeat(40));
+        let (line, column, message) = error(&huge);
+        assert_eq!((line, column), (1, 25));
+        assert!(message.ends_with("is too large"), "{}", message);
+    }
+}
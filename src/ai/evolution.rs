@@ -0,0 +1,303 @@
+//! Mutation and crossover operators for evolving rule sets.
+//!
+//! Every operator keeps the result a valid `RuleSet`: condition trees stay below
+//! `MAX_CONDITION_DEPTH`, rule sets keep between one and `MAX_RULES` rules and rule names
+//! stay unique, since the rule evaluator tells rules apart by name.
+
+use super::rules::{Action, Comparison, Condition, Placement, Rule, RuleSet, ITEMS};
+use crate::arena::areas::AreaID;
+use crate::building::StructureType;
+use rand::seq::IndexedRandom;
+use rand::Rng;
+
+pub const MAX_RULES: usize = 12;
+pub const MAX_CONDITION_DEPTH: usize = 4;
+
+/// Highest `IsHealthLow` threshold worth trying, players spawn with 3 HP.
+const MAX_HEALTH_THRESHOLD: u32 = 3;
+
+/// Mutates `rule_set` in place with one randomly picked operator.
+/// `areas` are the area IDs `MoveToArea` and `InArea` may refer to.
+pub fn mutate(rule_set: &mut RuleSet, areas: &[AreaID], rng: &mut impl Rng) {
+    if rule_set.rules.is_empty() {
+        rule_set
+            .rules
+            .push(random_rule(areas, rng, unused_name(&rule_set.rules)));
+        return;
+    }
+
+    let index = rng.random_range(0..rule_set.rules.len());
+    match rng.random_range(0..8) {
+        // Shift a priority
+        0 => rule_set.rules[index].priority += rng.random_range(-20..=20),
+        // Swap the actions of two rules
+        1 => {
+            let other = rng.random_range(0..rule_set.rules.len());
+            let action = rule_set.rules[other].action.clone();
+            let previous = std::mem::replace(&mut rule_set.rules[index].action, action);
+            rule_set.rules[other].action = previous;
+        }
+        // New action
+        2 => rule_set.rules[index].action = random_action(areas, rng),
+        // Tweak thresholds and counts
+        3 => tweak_numbers(&mut rule_set.rules[index].condition, rng),
+        // Combine with the condition of another rule
+        4 => {
+            let other = rule_set.rules[rng.random_range(0..rule_set.rules.len())]
+                .condition
+                .clone();
+            let condition = &mut rule_set.rules[index].condition;
+            if depth(condition).max(depth(&other)) < MAX_CONDITION_DEPTH {
+                let current = std::mem::replace(condition, Condition::True);
+                *condition = if rng.random_bool(0.5) {
+                    Condition::And(vec![current, other])
+                } else {
+                    Condition::Or(vec![current, other])
+                };
+            }
+        }
+        // Replace a random subtree
+        5 => {
+            let condition = &mut rule_set.rules[index].condition;
+            let target = rng.random_range(0..count_nodes(condition));
+            *nth_node(condition, target) = random_primitive(areas, rng);
+        }
+        // Add a rule
+        6 if rule_set.rules.len() < MAX_RULES => {
+            let name = unused_name(&rule_set.rules);
+            rule_set.rules.push(random_rule(areas, rng, name));
+        }
+        // Remove a rule
+        _ if rule_set.rules.len() > 1 => {
+            rule_set.rules.remove(index);
+        }
+        _ => rule_set.rules[index].priority += rng.random_range(-20..=20),
+    }
+}
+
+/// Picks every rule position from one of the parents, then swaps one condition subtree
+/// between the picked rules.
+pub fn crossover(a: &RuleSet, b: &RuleSet, rng: &mut impl Rng) -> RuleSet {
+    let len = if rng.random_bool(0.5) {
+        a.rules.len()
+    } else {
+        b.rules.len()
+    };
+
+    let mut rules: Vec<Rule> = (0..len)
+        .filter_map(|i| {
+            let (first, second) = if rng.random_bool(0.5) { (a, b) } else { (b, a) };
+            first.rules.get(i).or_else(|| second.rules.get(i)).cloned()
+        })
+        .collect();
+
+    // Both parents can have a rule of the same name at different positions.
+    for i in 0..rules.len() {
+        if rules[..i].iter().any(|rule| rule.name == rules[i].name) {
+            rules[i].name = unused_name(&rules);
+        }
+    }
+
+    if let (Some(donor), false) = (b.rules.choose(rng), rules.is_empty()) {
+        let index = rng.random_range(0..rules.len());
+        let donor_condition = &donor.condition;
+        let subtree = nth_node_ref(
+            donor_condition,
+            rng.random_range(0..count_nodes(donor_condition)),
+        );
+        let condition = &mut rules[index].condition;
+        let target = rng.random_range(0..count_nodes(condition));
+        let node = nth_node(condition, target);
+        let previous = std::mem::replace(node, subtree.clone());
+        if depth(condition) > MAX_CONDITION_DEPTH {
+            *nth_node(condition, target) = previous;
+        }
+    }
+
+    RuleSet {
+        rules,
+        strategy: a.strategy,
+    }
+}
+
+/// The first `EvolvedN` not used by any of `rules`.
+pub fn unused_name(rules: &[Rule]) -> String {
+    (0..)
+        .map(|id| format!("Evolved{}", id))
+        .find(|name| rules.iter().all(|rule| rule.name != *name))
+        .expect("some name is free")
+}
+
+pub fn random_rule(areas: &[AreaID], rng: &mut impl Rng, name: String) -> Rule {
+    Rule {
+        name,
+        priority: rng.random_range(0..=100),
+        condition: random_primitive(areas, rng),
+        action: random_action(areas, rng),
+        exit_condition: None,
+        min_duration: None,
+        cooldown: None,
+        score: None,
+    }
+}
+
+pub fn random_primitive(areas: &[AreaID], rng: &mut impl Rng) -> Condition {
+    match rng.random_range(0..8) {
+        0 => Condition::True,
+        1 => Condition::IsEnemyVisible,
+        2 => Condition::IsHealthLow {
+            threshold: rng.random_range(1..=MAX_HEALTH_THRESHOLD),
+        },
+        3 => match areas.choose(rng) {
+            Some(area) => Condition::InArea(area.clone()),
+            None => Condition::True,
+        },
+        4 => Condition::HasItem {
+            item: ITEMS.choose(rng).unwrap().0,
+            op: *[Comparison::AtLeast, Comparison::AtMost, Comparison::Equal]
+                .choose(rng)
+                .unwrap(),
+            count: rng.random_range(1..=3),
+        },
+        5 => Condition::EnemyWithinTiles(rng.random_range(1..=10)),
+        6 => match areas.choose(rng) {
+            Some(area) => Condition::AreaVisible(area.clone()),
+            None => Condition::True,
+        },
+        _ => Condition::IsUnderAttack,
+    }
+}
+
+pub fn random_action(areas: &[AreaID], rng: &mut impl Rng) -> Action {
+    match rng.random_range(0..5) {
+        0 => match areas.choose(rng) {
+            Some(area) => Action::MoveToArea(area.clone()),
+            None => Action::Idle,
+        },
+        1 => Action::ChaseEnemy,
+        2 => Action::Flee,
+        3 => Action::Build {
+            structure: *[StructureType::Turret, StructureType::Obstacle]
+                .choose(rng)
+                .unwrap(),
+            direction: None,
+            placement: *Placement::ALL.choose(rng).unwrap(),
+        },
+        _ => Action::Idle,
+    }
+}
+
+fn tweak_numbers(condition: &mut Condition, rng: &mut impl Rng) {
+    match condition {
+        Condition::IsHealthLow { threshold } => {
+            *threshold = threshold
+                .saturating_add_signed(rng.random_range(-1..=1))
+                .clamp(1, MAX_HEALTH_THRESHOLD);
+        }
+        Condition::HasItem { count, .. } | Condition::CounterAtLeast { value: count, .. } => {
+            *count = count.saturating_add_signed(rng.random_range(-1..=1));
+        }
+        Condition::EnemyWithinTiles(tiles)
+        | Condition::AreaDistanceBelow { tiles, .. }
+        | Condition::StructureNearby { radius: tiles, .. } => {
+            *tiles = tiles.saturating_add_signed(rng.random_range(-2..=2)).max(1);
+        }
+        Condition::And(conditions) | Condition::Or(conditions) => {
+            for condition in conditions {
+                tweak_numbers(condition, rng);
+            }
+        }
+        Condition::Not(condition) => tweak_numbers(condition, rng),
+        _ => {}
+    }
+}
+
+fn depth(condition: &Condition) -> usize {
+    match condition {
+        Condition::And(conditions) | Condition::Or(conditions) => {
+            1 + conditions.iter().map(depth).max().unwrap_or(0)
+        }
+        Condition::Not(condition) => 1 + depth(condition),
+        _ => 1,
+    }
+}
+
+fn count_nodes(condition: &Condition) -> usize {
+    let mut count = 0;
+    condition.visit(&mut |_| count += 1);
+    count
+}
+
+/// Node `n` of the tree in pre-order, the same order `Condition::visit` uses.
+/// `n` must be below `count_nodes(condition)`.
+fn nth_node(condition: &mut Condition, n: usize) -> &mut Condition {
+    fn find<'a>(condition: &'a mut Condition, n: &mut usize) -> Option<&'a mut Condition> {
+        if *n == 0 {
+            return Some(condition);
+        }
+        *n -= 1;
+        match condition {
+            Condition::And(conditions) | Condition::Or(conditions) => {
+                conditions.iter_mut().find_map(|c| find(c, n))
+            }
+            Condition::Not(condition) => find(condition, n),
+            _ => None,
+        }
+    }
+
+    find(condition, &mut { n }).expect("n is below the node count")
+}
+
+fn nth_node_ref(condition: &Condition, n: usize) -> &Condition {
+    fn find<'a>(condition: &'a Condition, n: &mut usize) -> Option<&'a Condition> {
+        if *n == 0 {
+            return Some(condition);
+        }
+        *n -= 1;
+        match condition {
+            Condition::And(conditions) | Condition::Or(conditions) => {
+                conditions.iter().find_map(|c| find(c, n))
+            }
+            Condition::Not(condition) => find(condition, n),
+            _ => None,
+        }
+    }
+
+    find(condition, &mut { n }).expect("n is below the node count")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn assert_unique_names(rule_set: &RuleSet) {
+        for (i, rule) in rule_set.rules.iter().enumerate() {
+            assert!(
+                rule_set.rules[..i]
+                    .iter()
+                    .all(|other| other.name != rule.name),
+                "duplicate rule name `{}`",
+                rule.name
+            );
+        }
+    }
+
+    #[test]
+    fn rule_names_stay_unique() {
+        let areas = [AreaID("A".to_string()), AreaID("B".to_string())];
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut a = RuleSet::default();
+        let mut b = RuleSet::new_turret_only();
+        for _ in 0..500 {
+            mutate(&mut a, &areas, &mut rng);
+            mutate(&mut b, &areas, &mut rng);
+            let child = crossover(&a, &b, &mut rng);
+            assert_unique_names(&a);
+            assert_unique_names(&b);
+            assert_unique_names(&child);
+            b = child;
+        }
+    }
+}
@@ -1,35 +1,70 @@
 use crate::arena::areas::AreaMap;
-use crate::arena::{ArenaConfig, ArenaGrid, Obstacle};
-use crate::building::{Structure, StructureType};
-use crate::combat::{Hp, Turret, TurretDirection};
+use crate::arena::{ArenaConfig, ArenaGrid, Collectible, CollectibleType, Obstacle};
+use crate::building::{Structure, StructureType, BUILD_MAX_DISTANCE};
+use crate::combat::{DamageHistory, DamageTracking, Hp, Turret, TurretDirection};
 use crate::logging::{GameEvent, MatchLog};
 use crate::pathfinding::{find_path, NavGraph};
-use crate::player::{Inventory, MovementController};
+use crate::player::{Inventory, MovementController, PlayerStatus};
 use crate::player_id::PlayerID;
+use crate::simulation::SimulationSet;
+use bevy::ecs::system::SystemParam;
+use bevy::platform::collections::HashMap;
 use bevy::prelude::*;
 
+pub mod assets;
+pub mod behavior;
+pub mod blackboard;
+pub mod dsl;
+pub mod evolution;
+pub mod placement;
 pub mod rules;
+pub mod trace;
+pub mod validation;
 
-use rules::{Action, Condition, RuleSet};
+use behavior::{BehaviorMemory, BehaviorTree, Node, NodeStatus};
+use blackboard::{Blackboard, LAST_SEEN_ENEMY};
+use placement::Surroundings;
+use rules::{Action, Condition, RuleSet, Score, Strategy};
+use trace::{
+    Check, ConditionTrace, DecisionTrace, DecisionTraces, DecisionTracing, Observations, RuleTrace,
+};
 
 pub struct AiPlugin;
 
 impl Plugin for AiPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
-            Update,
+            FixedUpdate,
             (
+                blackboard::update_blackboards,
+                rule_evaluation_system,
+                behavior_tree_system,
                 pathfinding_system,
                 path_following_system,
-                rule_evaluation_system,
-            ),
+            )
+                .chain()
+                .in_set(SimulationSet::Ai),
         );
+        app.init_resource::<DecisionLogging>()
+            .init_resource::<DecisionTracing>()
+            .init_resource::<DecisionTraces>();
         // AreaMap is now initialized by ArenaPlugin
         // app.init_resource::<AreaMap>();
     }
 }
 
+/// Which `GameEvent::AiDecision`s `rule_evaluation_system` adds to the match log.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecisionLogging {
+    /// One event whenever an AI selects another rule, or stops executing one.
+    #[default]
+    Changes,
+    /// One event for every rule checked, every time an AI thinks.
+    FullTrace,
+}
+
 #[derive(Component)]
+#[require(Blackboard, ActionProgress)]
 pub struct AiPlayer;
 
 #[derive(Component)]
@@ -45,292 +80,953 @@ pub struct PathFollower {
 }
 
 #[derive(Component, Default)]
+#[require(RuleEvaluatorState)]
 pub struct AiRuleSet(pub RuleSet);
 
-fn evaluate_condition(
-    condition: &Condition,
-    status: &PlayerStatus,
-    hp: &Hp,
-    inventory: &Inventory,
-) -> bool {
+/// How many times per second an AI evaluates its rules. In between, it keeps executing the
+/// selected rule. AIs without one evaluate them every tick.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ThinkRate(pub f32);
+
+/// What `rule_evaluation_system` remembers about an AI between ticks, for the
+/// `exit_condition`, `min_duration` and `cooldown` of its rules. Rules are kept by name, so
+/// the state carries over when the rule set is replaced.
+#[derive(Component, Default)]
+pub struct RuleEvaluatorState {
+    /// Name of the selected rule and the time it was picked.
+    pub selected: Option<(String, f32)>,
+    /// Time from which a rule, by name, can be picked again.
+    pub cooldowns: HashMap<String, f32>,
+    /// Indices of the rules by descending priority, rebuilt when the rule set changes.
+    pub order: Vec<usize>,
+    /// Time of the next evaluation, see `ThinkRate`.
+    pub next_think: f32,
+    /// The selected rule only writes the blackboard and already did, see
+    /// `Action::writes_blackboard_only`.
+    pub applied: bool,
+}
+
+/// Replaces the `AiRuleSet` of an AI while present.
+#[derive(Component)]
+#[require(BehaviorTreeState)]
+pub struct AiBehaviorTree(pub BehaviorTree);
+
+/// Progress through the `AiBehaviorTree`, kept between ticks.
+#[derive(Component, Default)]
+pub struct BehaviorTreeState {
+    pub memory: BehaviorMemory,
+}
+
+/// How far the AI got with the compound `Action` it executed last: the step of every nested
+/// `Sequence` and the next area of a `PatrolAreas`, outermost first. Starts over whenever a
+/// different action is executed.
+#[derive(Component, Default)]
+pub struct ActionProgress {
+    action: Option<Action>,
+    cursors: Vec<usize>,
+}
+
+impl ActionProgress {
+    fn cursor(&mut self, depth: usize) -> &mut usize {
+        if self.cursors.len() <= depth {
+            self.cursors.resize(depth + 1, 0);
+        }
+        &mut self.cursors[depth]
+    }
+}
+
+/// Everything a condition can look at for one AI player.
+pub struct ConditionContext<'a> {
+    pub status: &'a PlayerStatus,
+    pub hp: &'a Hp,
+    pub inventory: &'a Inventory,
+    pub damage: &'a DamageHistory,
+    pub position: Vec3,
+    pub config: &'a ArenaConfig,
+    pub area_map: &'a AreaMap,
+    /// Type and position of every structure in the arena.
+    pub structures: &'a [(StructureType, Vec3)],
+    pub blackboard: &'a Blackboard,
+    /// Gameplay clock, in seconds.
+    pub time: f32,
+    pub under_attack_window: f32,
+}
+
+fn item_count(inventory: &Inventory, item: CollectibleType) -> u32 {
+    match item {
+        CollectibleType::Obstacle => inventory.obstacles,
+        CollectibleType::Turret => inventory.turrets,
+    }
+}
+
+fn evaluate_condition(condition: &Condition, context: &ConditionContext) -> bool {
+    let status = context.status;
+    let inventory = context.inventory;
     let result = match condition {
         Condition::True => true,
         Condition::IsEnemyVisible => !status.visible_players.is_empty(),
-        Condition::IsHealthLow { threshold } => hp.current <= *threshold,
+        Condition::IsHealthLow { threshold } => context.hp.current <= *threshold,
         Condition::InArea(area_id) => status.current_area_id.as_ref() == Some(area_id),
-        Condition::HasItem { item, count } => {
-            let has = match item.as_str() {
-                "obstacle" => inventory.obstacles >= *count,
-                "turret" => inventory.turrets >= *count,
-                _ => false,
-            };
-            info!("Checking HasItem: {} >= {} -> {}", item, count, has);
+        Condition::HasItem { item, op, count } => {
+            let has = op.matches(item_count(inventory, *item), *count);
+            trace!("Checking HasItem: {:?} {} {} -> {}", item, op, count, has);
             has
         }
-        Condition::IsUnderAttack => false, // TODO: Implement attack detection
-        Condition::And(conditions) => conditions
-            .iter()
-            .all(|c| evaluate_condition(c, status, hp, inventory)),
-        Condition::Or(conditions) => conditions
-            .iter()
-            .any(|c| evaluate_condition(c, status, hp, inventory)),
-        Condition::Not(condition) => !evaluate_condition(condition, status, hp, inventory),
+        Condition::IsUnderAttack => context
+            .damage
+            .hit_within(context.time, context.under_attack_window),
+        Condition::EnemyWithinTiles(tiles) => {
+            status.nearest_enemy_position.is_some()
+                && status.nearest_enemy_dist <= *tiles as f32 * context.config.tile_size
+        }
+        Condition::AreaDistanceBelow { area, tiles } => status
+            .area_distances
+            .get(area)
+            .is_some_and(|distance| distance < tiles),
+        Condition::EnemyInArea(area_id) => status.nearest_enemy_position.is_some_and(|enemy_pos| {
+            let (x, y) = context.config.world_to_tile(enemy_pos);
+            context.area_map.get_area_id(x, y).as_ref() == Some(area_id)
+        }),
+        Condition::AreaVisible(area_id) => status.visible_areas_from_self.contains(area_id),
+        Condition::StructureNearby { ty, radius } => {
+            let max_distance = *radius as f32 * context.config.tile_size;
+            context.structures.iter().any(|(structure_ty, position)| {
+                structure_ty == ty && position.xz().distance(context.position.xz()) <= max_distance
+            })
+        }
+        Condition::FlagSet(flag) => context.blackboard.flag(flag),
+        Condition::CounterAtLeast { counter, value } => {
+            context.blackboard.counter(counter) >= *value
+        }
+        Condition::And(conditions) => conditions.iter().all(|c| evaluate_condition(c, context)),
+        Condition::Or(conditions) => conditions.iter().any(|c| evaluate_condition(c, context)),
+        Condition::Not(condition) => !evaluate_condition(condition, context),
     };
     result
 }
 
+fn evaluate_score(score: &Score, context: &ConditionContext) -> f32 {
+    let fold = |scores: &[Score], f: fn(f32, f32) -> f32| {
+        scores
+            .iter()
+            .map(|score| evaluate_score(score, context))
+            .reduce(f)
+    };
+    match score {
+        Score::Constant(value) => *value,
+        Score::EnemyDistance => {
+            if context.status.nearest_enemy_position.is_some() {
+                context.status.nearest_enemy_dist / context.config.tile_size
+            } else {
+                (context.config.width as f32).hypot(context.config.height as f32)
+            }
+        }
+        Score::HpRatio => context.hp.current as f32 / context.hp.max.max(1) as f32,
+        Score::ItemCount { item } => item_count(context.inventory, *item) as f32,
+        Score::VisibleEnemies => context.status.visible_players.len() as f32,
+        Score::Add(scores) => fold(scores, |a, b| a + b).unwrap_or(0.0),
+        Score::Mul(scores) => fold(scores, |a, b| a * b).unwrap_or(1.0),
+        Score::Min(scores) => fold(scores, f32::min).unwrap_or(0.0),
+        Score::Max(scores) => fold(scores, f32::max).unwrap_or(0.0),
+        Score::If {
+            condition,
+            then,
+            otherwise,
+        } => {
+            if evaluate_condition(condition, context) {
+                evaluate_score(then, context)
+            } else {
+                evaluate_score(otherwise, context)
+            }
+        }
+    }
+}
+
+/// Marks an AI player driven from outside the game, e.g. by `gym::Env`. Its rule set is not
+/// evaluated; `action` is executed once on the next tick instead.
+#[derive(Component, Default)]
+pub struct ExternalControl {
+    pub action: Option<Action>,
+    /// The controller drives `MovementController` itself, so path following is suspended.
+    pub manual_movement: bool,
+}
+
+/// The AI player an `Action` is carried out for.
+struct Actor<'a> {
+    player_id: &'a PlayerID,
+    status: &'a PlayerStatus,
+    transform: &'a Transform,
+    inventory: &'a mut Inventory,
+    target: &'a mut TargetDestination,
+    blackboard: &'a mut Blackboard,
+    progress: &'a mut ActionProgress,
+}
+
+/// World access needed to carry out an `Action`.
+#[derive(SystemParam)]
+pub struct ActionContext<'w, 's> {
+    commands: Commands<'w, 's>,
+    area_map: Res<'w, AreaMap>,
+    config: Res<'w, ArenaConfig>,
+    grid: ResMut<'w, ArenaGrid>,
+    nav_graph: ResMut<'w, NavGraph>,
+    match_log: ResMut<'w, MatchLog>,
+    time: Res<'w, Time>,
+    collectible_query: Query<'w, 's, (&'static Collectible, &'static Transform)>,
+    structure_query: Query<'w, 's, &'static Structure>,
+}
+
 fn rule_evaluation_system(
-    mut commands: Commands,
     mut query: Query<
         (
             Entity,
             &Name,
-            &AiRuleSet,
+            Ref<AiRuleSet>,
+            Option<&ThinkRate>,
             &PlayerStatus,
             &Hp,
             &mut Inventory,
             &Transform,
             &mut TargetDestination,
             &PlayerID,
+            &DamageHistory,
+            &mut RuleEvaluatorState,
+            &mut Blackboard,
+            &mut ActionProgress,
+            Option<&mut ExternalControl>,
         ),
-        With<AiPlayer>,
+        (With<AiPlayer>, Without<AiBehaviorTree>),
     >,
-    area_map: Res<AreaMap>,
-    config: Res<ArenaConfig>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut grid: ResMut<ArenaGrid>,
-    mut nav_graph: ResMut<NavGraph>,
-    mut match_log: ResMut<MatchLog>,
-    time: Res<Time>,
+    structure_query: Query<(&Structure, &Transform)>,
+    damage_tracking: Res<DamageTracking>,
+    logging: Res<DecisionLogging>,
+    tracing: Res<DecisionTracing>,
+    mut traces: ResMut<DecisionTraces>,
+    mut context: ActionContext,
 ) {
-    for (entity, name, rule_set, status, hp, mut inventory, transform, mut target, player_id) in
-        query.iter_mut()
+    let structures: Vec<(StructureType, Vec3)> = structure_query
+        .iter()
+        .map(|(structure, transform)| (structure.ty, transform.translation))
+        .collect();
+
+    for (
+        entity,
+        name,
+        rule_set,
+        think_rate,
+        status,
+        hp,
+        mut inventory,
+        transform,
+        mut target,
+        player_id,
+        damage,
+        mut state,
+        mut blackboard,
+        mut progress,
+        external,
+    ) in query.iter_mut()
     {
-        // Sort rules by priority (descending)
-        let mut sorted_rules = rule_set.0.rules.clone();
-        sorted_rules.sort_by(|a, b| b.priority.cmp(&a.priority));
+        if let Some(mut external) = external {
+            if let Some(action) = external.action.take() {
+                let actor = Actor {
+                    player_id,
+                    status,
+                    transform,
+                    inventory: &mut inventory,
+                    target: &mut target,
+                    blackboard: &mut blackboard,
+                    progress: &mut progress,
+                };
+                execute_action(&action, actor, &mut context);
+            }
+            continue;
+        }
 
-        for rule in sorted_rules {
-            let condition_met = evaluate_condition(&rule.condition, status, hp, &inventory);
+        let rules = &rule_set.0.rules;
+        let now = context.time.elapsed_secs();
+
+        // A new rule set is sorted once and evaluated right away.
+        if rule_set.is_changed() {
+            let mut order: Vec<usize> = (0..rules.len()).collect();
+            order.sort_by(|a, b| rules[*b].priority.cmp(&rules[*a].priority));
+            state.order = order;
+            state.next_think = now;
+        }
 
-            match_log.add(GameEvent::AiDecision {
+        if now >= state.next_think {
+            if let Some(ThinkRate(rate)) = think_rate.filter(|rate| rate.0 > 0.0) {
+                state.next_think = now + 1.0 / rate;
+            }
+            state.cooldowns.retain(|_, until| *until > now);
+
+            let condition_context = ConditionContext {
+                status,
+                hp,
+                inventory: &inventory,
+                damage,
+                position: transform.translation,
+                config: &context.config,
+                area_map: &context.area_map,
+                structures: &structures,
+                blackboard: &blackboard,
+                time: now,
+                under_attack_window: damage_tracking.under_attack_window,
+            };
+            let decision = |rule_name: &str, condition_met, score, trace| GameEvent::AiDecision {
                 entity: *player_id,
                 entity_name: name.to_string(),
-                rule_name: rule.name.clone(),
+                rule_name: rule_name.to_string(),
                 condition_met,
-                inventory_obstacles: inventory.obstacles,
-                inventory_turrets: inventory.turrets,
+                score,
+                inventory_obstacles: condition_context.inventory.obstacles,
+                inventory_turrets: condition_context.inventory.turrets,
                 visible_enemies: status.visible_players.len(),
-                time: time.elapsed_secs(),
-            });
+                time: now,
+                trace,
+            };
+            // Traced evaluation looks at every part of a condition, so it's only done on
+            // request. Without it, `check` stays `None`.
+            let traced = *tracing != DecisionTracing::Off;
+            let evaluate = |condition: &Condition| {
+                if traced {
+                    let trace = ConditionTrace::evaluate(condition, &condition_context);
+                    (trace.result(), Some(trace))
+                } else {
+                    (evaluate_condition(condition, &condition_context), None)
+                }
+            };
+            let mut rule_traces = Vec::new();
 
-            if condition_met {
-                info!("AI {:?} ({}) executing rule: {}", entity, name, rule.name);
-                match &rule.action {
-                    Action::MoveToArea(area_id) => {
-                        if let Some((x, y)) = area_map.get_center(area_id.clone()) {
-                            if target.x != x || target.y != y {
-                                target.x = x;
-                                target.y = y;
-                                // info!("AI {:?} moving to area {:?}", entity, area_id);
-                            }
+            let strategy = rule_set.0.strategy;
+            let mut selected_rule = None;
+            let mut best_score = f32::NEG_INFINITY;
+            for rule in state.order.iter().map(|index| &rules[*index]) {
+                let selected_since = match &state.selected {
+                    Some((name, since)) if *name == rule.name => Some(*since),
+                    _ => None,
+                };
+                let committed = selected_since
+                    .is_some_and(|since| now - since < rule.min_duration.unwrap_or(0.0));
+
+                // The selected rule holds until committed time is up and it exits.
+                let (condition_met, check) = match &rule.exit_condition {
+                    _ if committed => (true, traced.then_some(Check::Committed)),
+                    Some(exit_condition) if selected_since.is_some() => {
+                        let (exit, trace) = evaluate(exit_condition);
+                        (!exit, trace.map(Check::ExitCondition))
+                    }
+                    _ if selected_since.is_none() && state.cooldowns.contains_key(&rule.name) => {
+                        if traced {
+                            rule_traces.push(RuleTrace {
+                                rule: rule.name.clone(),
+                                check: Check::Cooldown,
+                                condition_met: false,
+                                score: None,
+                            });
                         }
+                        continue;
                     }
-                    Action::ChaseEnemy => {
-                        if let Some(enemy_pos) = status.nearest_enemy_position {
-                            let x = ((enemy_pos.x - config.tile_size * 0.5) / config.tile_size)
-                                .floor() as u32;
-                            let y = ((enemy_pos.z - config.tile_size * 0.5) / config.tile_size)
-                                .floor() as u32;
-
-                            if target.x != x || target.y != y {
-                                target.x = x;
-                                target.y = y;
-                                // info!("AI {:?} chasing enemy at ({}, {})", entity, x, y);
-                            }
+                    _ => {
+                        let (condition_met, trace) = evaluate(&rule.condition);
+                        (condition_met, trace.map(Check::Condition))
+                    }
+                };
+
+                let score = match (strategy, &rule.score) {
+                    (Strategy::Utility, Some(score)) if condition_met => {
+                        Some(evaluate_score(score, &condition_context))
+                    }
+                    (Strategy::Utility, None) if condition_met => Some(rule.priority as f32),
+                    _ => None,
+                };
+
+                let trace = check.map(|check| RuleTrace {
+                    rule: rule.name.clone(),
+                    check,
+                    condition_met,
+                    score,
+                });
+                if *logging == DecisionLogging::FullTrace {
+                    let attached = trace
+                        .clone()
+                        .filter(|_| *tracing == DecisionTracing::Attach);
+                    context
+                        .match_log
+                        .add(decision(&rule.name, condition_met, score, attached));
+                }
+                rule_traces.extend(trace);
+
+                match strategy {
+                    Strategy::Priority => {
+                        if condition_met {
+                            selected_rule = Some((rule, score));
+                            break; // Execute only the highest priority rule
                         }
                     }
-                    Action::Flee => {
-                        // Simple flee: Run to opposite corner of nearest enemy
-                        if let Some(enemy_pos) = status.nearest_enemy_position {
-                            let my_x = ((transform.translation.x - config.tile_size * 0.5)
-                                / config.tile_size)
-                                .floor() as u32;
-                            let my_y = ((transform.translation.z - config.tile_size * 0.5)
-                                / config.tile_size)
-                                .floor() as u32;
-
-                            let enemy_x = ((enemy_pos.x - config.tile_size * 0.5)
-                                / config.tile_size)
-                                .floor() as u32;
-                            let enemy_y = ((enemy_pos.z - config.tile_size * 0.5)
-                                / config.tile_size)
-                                .floor() as u32;
-
-                            // Vector away from enemy
-                            let dx = my_x as i32 - enemy_x as i32;
-                            let dy = my_y as i32 - enemy_y as i32;
-
-                            let flee_x = (my_x as i32 + dx).clamp(0, config.width as i32) as u32;
-                            let flee_y = (my_y as i32 + dy).clamp(0, config.height as i32) as u32;
-
-                            if target.x != flee_x || target.y != flee_y {
-                                target.x = flee_x;
-                                target.y = flee_y;
-                                // info!("AI {:?} fleeing to ({}, {})", entity, flee_x, flee_y);
-                            }
+                    Strategy::Utility => {
+                        if committed {
+                            selected_rule = Some((rule, score));
+                            break;
                         }
+                        // Ties go to the higher priority rule.
+                        if let Some(score) = score.filter(|score| *score > best_score) {
+                            best_score = score;
+                            selected_rule = Some((rule, Some(score)));
+                        }
+                    }
+                }
+            }
+
+            let previous = state.selected.as_ref().map(|(name, _)| name);
+            if previous != selected_rule.map(|(rule, _)| &rule.name) {
+                if *logging == DecisionLogging::Changes {
+                    let attached = |rule_name: &str| {
+                        rule_traces
+                            .iter()
+                            .find(|trace| trace.rule == rule_name)
+                            .filter(|_| *tracing == DecisionTracing::Attach)
+                            .cloned()
+                    };
+                    let event = match (selected_rule, previous) {
+                        (Some((rule, score)), _) => {
+                            Some(decision(&rule.name, true, score, attached(&rule.name)))
+                        }
+                        (None, Some(previous)) => {
+                            Some(decision(previous, false, None, attached(previous)))
+                        }
+                        (None, None) => None,
+                    };
+                    if let Some(event) = event {
+                        context.match_log.add(event);
+                    }
+                }
+                if let Some((rule, _)) = selected_rule {
+                    info!("AI {:?} ({}) executing rule: {}", entity, name, rule.name);
+                }
+
+                if let Some((name, _)) = state.selected.take() {
+                    let cooldown = rules
+                        .iter()
+                        .find(|rule| rule.name == name)
+                        .and_then(|rule| rule.cooldown);
+                    if let Some(cooldown) = cooldown {
+                        state.cooldowns.insert(name, now + cooldown);
+                    }
+                }
+                state.selected = selected_rule.map(|(rule, _)| (rule.name.clone(), now));
+                state.applied = false;
+            }
+
+            if traced {
+                let trace = DecisionTrace {
+                    time: now,
+                    observations: Observations::new(&condition_context),
+                    rules: rule_traces,
+                    selected: selected_rule.map(|(rule, _)| rule.name.clone()),
+                };
+                traces.push(*player_id, trace);
+            }
+        }
+
+        // Between evaluations the selected rule keeps running.
+        let selected_rule = state
+            .selected
+            .as_ref()
+            .and_then(|(selected, _)| rules.iter().find(|rule| rule.name == *selected));
+        if let Some(rule) = selected_rule.filter(|_| !state.applied) {
+            let actor = Actor {
+                player_id,
+                status,
+                transform,
+                inventory: &mut inventory,
+                target: &mut target,
+                blackboard: &mut blackboard,
+                progress: &mut progress,
+            };
+            execute_action(&rule.action, actor, &mut context);
+            state.applied = rule.action.writes_blackboard_only();
+        }
+    }
+}
+
+fn behavior_tree_system(
+    mut query: Query<
+        (
+            Entity,
+            &Name,
+            Ref<AiBehaviorTree>,
+            &mut BehaviorTreeState,
+            &PlayerStatus,
+            &Hp,
+            &mut Inventory,
+            &Transform,
+            &mut TargetDestination,
+            &PlayerID,
+            &DamageHistory,
+            &mut Blackboard,
+            &mut ActionProgress,
+        ),
+        (With<AiPlayer>, Without<ExternalControl>),
+    >,
+    structure_query: Query<(&Structure, &Transform)>,
+    damage_tracking: Res<DamageTracking>,
+    mut context: ActionContext,
+) {
+    let structures: Vec<(StructureType, Vec3)> = structure_query
+        .iter()
+        .map(|(structure, transform)| (structure.ty, transform.translation))
+        .collect();
+
+    for (
+        entity,
+        name,
+        tree,
+        mut state,
+        status,
+        hp,
+        mut inventory,
+        transform,
+        mut target,
+        player_id,
+        damage,
+        mut blackboard,
+        mut progress,
+    ) in query.iter_mut()
+    {
+        // A new tree starts from scratch.
+        if tree.is_changed() {
+            state.memory.reset();
+        }
+
+        let now = context.time.elapsed_secs();
+        let root_status = tree
+            .0
+            .tick(&mut state.memory, &mut |node: &Node| match node {
+                Node::Condition(condition) => {
+                    let condition_context = ConditionContext {
+                        status,
+                        hp,
+                        inventory: &inventory,
+                        damage,
+                        position: transform.translation,
+                        config: &context.config,
+                        area_map: &context.area_map,
+                        structures: &structures,
+                        blackboard: &blackboard,
+                        time: now,
+                        under_attack_window: damage_tracking.under_attack_window,
+                    };
+                    if evaluate_condition(condition, &condition_context) {
+                        NodeStatus::Success
+                    } else {
+                        NodeStatus::Failure
+                    }
+                }
+                Node::Action(action) => {
+                    let actor = Actor {
+                        player_id,
+                        status,
+                        transform,
+                        inventory: &mut inventory,
+                        target: &mut target,
+                        blackboard: &mut blackboard,
+                        progress: &mut progress,
+                    };
+                    execute_action(action, actor, &mut context)
+                }
+                _ => unreachable!("only leaves are passed to the leaf callback"),
+            });
+
+        // Trees that finish every tick would flood the log at info level.
+        if root_status != NodeStatus::Running {
+            debug!(
+                "AI {:?} ({}) behavior tree finished: {:?}",
+                entity, name, root_status
+            );
+        }
+    }
+}
+
+fn execute_action(action: &Action, mut actor: Actor, context: &mut ActionContext) -> NodeStatus {
+    if actor.progress.action.as_ref() != Some(action) {
+        actor.progress.action = Some(action.clone());
+        actor.progress.cursors.clear();
+    }
+    execute_step(action, 0, &mut actor, context)
+}
+
+/// Executes `action`, nested `depth` compound actions deep.
+fn execute_step(
+    action: &Action,
+    depth: usize,
+    actor: &mut Actor,
+    context: &mut ActionContext,
+) -> NodeStatus {
+    match action {
+        Action::Sequence(steps) => {
+            while let Some(step) = steps.get(*actor.progress.cursor(depth)) {
+                match execute_step(step, depth + 1, actor, context) {
+                    NodeStatus::Success => {
+                        // Instant steps don't hold up the next one.
+                        *actor.progress.cursor(depth) += 1;
+                        actor.progress.cursors.truncate(depth + 1);
+                    }
+                    NodeStatus::Running => return NodeStatus::Running,
+                    NodeStatus::Failure => {
+                        actor.progress.cursors.truncate(depth);
+                        return NodeStatus::Failure;
+                    }
+                }
+            }
+            actor.progress.cursors.truncate(depth);
+            NodeStatus::Success
+        }
+        Action::PatrolAreas(areas) => {
+            if areas.is_empty() {
+                return NodeStatus::Failure;
+            }
+            let index = *actor.progress.cursor(depth) % areas.len();
+            let step = Action::MoveToArea(areas[index].clone());
+            match execute_step(&step, depth + 1, actor, context) {
+                NodeStatus::Success => {
+                    *actor.progress.cursor(depth) = (index + 1) % areas.len();
+                    NodeStatus::Running
+                }
+                status => status,
+            }
+        }
+        Action::GuardArea { area, radius } => {
+            let Some((x, y)) = context.area_map.get_center(area.clone()) else {
+                return NodeStatus::Failure;
+            };
+            let center = context.config.tile_to_world(x, y);
+            let max_distance = *radius as f32 * context.config.tile_size;
+            let intruder = actor
+                .status
+                .nearest_enemy_position
+                .is_some_and(|enemy_pos| enemy_pos.xz().distance(center.xz()) <= max_distance);
+
+            let step = if intruder {
+                Action::ChaseEnemy
+            } else {
+                Action::MoveToArea(area.clone())
+            };
+            execute_step(&step, depth + 1, actor, context);
+            // Guarding never ends.
+            NodeStatus::Running
+        }
+        _ => execute_primitive(action, actor, context),
+    }
+}
+
+/// Sets the target only if it moved, so the path isn't recomputed every tick.
+fn set_target(target: &mut TargetDestination, x: u32, y: u32) {
+    if target.x != x || target.y != y {
+        target.x = x;
+        target.y = y;
+    }
+}
+
+fn execute_primitive(
+    action: &Action,
+    actor: &mut Actor,
+    context: &mut ActionContext,
+) -> NodeStatus {
+    let player_id = actor.player_id;
+    let status = actor.status;
+    let transform = actor.transform;
+    let inventory = &mut *actor.inventory;
+    let target = &mut *actor.target;
+    let blackboard = &mut *actor.blackboard;
+    let config = &*context.config;
+    match action {
+        Action::MoveToArea(area_id) => {
+            let Some((x, y)) = context.area_map.get_center(area_id.clone()) else {
+                return NodeStatus::Failure;
+            };
+            set_target(target, x, y);
+            if status.current_area_id.as_ref() == Some(area_id) {
+                NodeStatus::Success
+            } else {
+                NodeStatus::Running
+            }
+        }
+        Action::ChaseEnemy => {
+            let Some(enemy_pos) = status.nearest_enemy_position else {
+                return NodeStatus::Failure;
+            };
+            let (x, y) = config.world_to_tile(enemy_pos);
+            set_target(target, x, y);
+            // Caught up once the enemy is on a neighboring tile.
+            if status.nearest_enemy_dist <= config.tile_size {
+                NodeStatus::Success
+            } else {
+                NodeStatus::Running
+            }
+        }
+        Action::Flee => {
+            // Simple flee: Run to opposite corner of nearest enemy
+            if let Some(enemy_pos) = status.nearest_enemy_position {
+                let (my_x, my_y) = config.world_to_tile(transform.translation);
+                let (enemy_x, enemy_y) = config.world_to_tile(enemy_pos);
+
+                // Vector away from enemy
+                let dx = my_x as i32 - enemy_x as i32;
+                let dy = my_y as i32 - enemy_y as i32;
+
+                let flee_x = (my_x as i32 + dx).clamp(0, config.width as i32) as u32;
+                let flee_y = (my_y as i32 + dy).clamp(0, config.height as i32) as u32;
+
+                set_target(target, flee_x, flee_y);
+                NodeStatus::Running
+            } else {
+                // Nobody left to flee from.
+                NodeStatus::Success
+            }
+        }
+        Action::Build {
+            structure,
+            direction,
+            placement,
+        } => {
+            let enemy = match status.nearest_enemy_position {
+                Some(enemy_pos) => Some(config.world_to_tile(enemy_pos)),
+                None => blackboard.positions.get(LAST_SEEN_ENEMY).copied(),
+            };
+            let surroundings = Surroundings {
+                config,
+                nav_graph: &context.nav_graph,
+                from: config.world_to_tile(transform.translation),
+                enemy,
+                destination: (target.x, target.y),
+            };
+            let Some(site) = placement::plan(*placement, *structure, *direction, &surroundings)
+            else {
+                return NodeStatus::Failure;
+            };
+            let direction = &site.direction;
+            let (tile_x, tile_y) = site.tile;
+
+            // Check if tile is occupied
+            if context.grid.occupants.contains_key(&(tile_x, tile_y)) {
+                return NodeStatus::Failure;
+            }
+
+            let position = Vec3::new(
+                tile_x as f32 * config.tile_size + config.tile_size * 0.5,
+                0.0,
+                tile_y as f32 * config.tile_size + config.tile_size * 0.5,
+            );
+
+            match structure {
+                StructureType::Obstacle => {
+                    if inventory.obstacles > 0 {
+                        inventory.obstacles -= 1;
+                        let obstacle_entity = context
+                            .commands
+                            .spawn((
+                                Obstacle,
+                                Structure {
+                                    ty: StructureType::Obstacle,
+                                    collider_scale: 1.0,
+                                },
+                                Transform::from_translation(position + Vec3::Y * (8.0 * 0.4)),
+                            ))
+                            .id();
+                        context
+                            .grid
+                            .occupants
+                            .insert((tile_x, tile_y), obstacle_entity);
+                        crate::arena::regenerate_nav_graph(
+                            config,
+                            &context.grid,
+                            &mut context.nav_graph,
+                        );
+                        context.match_log.add(GameEvent::StructureBuilt {
+                            entity: *player_id,
+                            structure: StructureType::Obstacle,
+                            location: (tile_x, tile_y),
+                            time: context.time.elapsed_secs(),
+                        });
+                        blackboard.record_built(StructureType::Obstacle);
+                        info!("AI Built Obstacle at ({}, {})", tile_x, tile_y);
+                        return NodeStatus::Success;
                     }
-                    Action::Build {
-                        structure,
-                        direction,
-                    } => {
-                        let tile_x = ((transform.translation.x - config.tile_size * 0.5)
-                            / config.tile_size)
-                            .floor() as u32;
-                        let tile_y = ((transform.translation.z - config.tile_size * 0.5)
-                            / config.tile_size)
-                            .floor() as u32;
-
-                        // Check if tile is occupied
-                        if !grid.occupants.contains_key(&(tile_x, tile_y)) {
-                            let position = Vec3::new(
-                                tile_x as f32 * config.tile_size + config.tile_size * 0.5,
-                                0.0,
-                                tile_y as f32 * config.tile_size + config.tile_size * 0.5,
-                            );
-
-                            match structure {
-                                StructureType::Obstacle => {
-                                    if inventory.obstacles > 0 {
-                                        inventory.obstacles -= 1;
-                                        let obstacle_entity = commands
-                                            .spawn((
-                                                Obstacle,
-                                                Structure {
-                                                    ty: StructureType::Obstacle,
-                                                    collider_scale: 1.0,
-                                                },
-                                                Mesh3d(meshes.add(Cuboid::new(
-                                                    config.tile_size * 0.8,
-                                                    8.0 * 0.8,
-                                                    config.tile_size * 0.8,
-                                                ))),
-                                                MeshMaterial3d(
-                                                    materials.add(Color::srgb(0.6, 0.3, 0.3)),
-                                                ),
-                                                Transform::from_translation(
-                                                    position + Vec3::Y * (8.0 * 0.4),
-                                                ),
-                                            ))
-                                            .id();
-                                        grid.occupants.insert((tile_x, tile_y), obstacle_entity);
-                                        crate::arena::regenerate_nav_graph(
-                                            &config,
-                                            &grid,
-                                            &mut nav_graph,
-                                        );
-                                        match_log.add(GameEvent::StructureBuilt {
-                                            entity: *player_id,
-                                            structure: StructureType::Obstacle,
-                                            location: (tile_x, tile_y),
-                                            time: time.elapsed_secs(),
-                                        });
-                                        info!("AI Built Obstacle at ({}, {})", tile_x, tile_y);
+                }
+                StructureType::Turret => {
+                    if inventory.turrets > 0 {
+                        inventory.turrets -= 1;
+
+                        let turret_dir = if let Some(dir) = direction {
+                            *dir
+                        } else {
+                            // Face enemy if possible, else random or South
+                            if let Some(enemy_pos) = status.nearest_enemy_position {
+                                let to_enemy = enemy_pos - position;
+                                // Determine cardinal direction
+                                if to_enemy.x.abs() > to_enemy.z.abs() {
+                                    if to_enemy.x > 0.0 {
+                                        TurretDirection::East
+                                    } else {
+                                        TurretDirection::West
                                     }
-                                }
-                                StructureType::Turret => {
-                                    if inventory.turrets > 0 {
-                                        inventory.turrets -= 1;
-
-                                        let turret_dir = if let Some(dir) = direction {
-                                            *dir
-                                        } else {
-                                            // Face enemy if possible, else random or South
-                                            if let Some(enemy_pos) = status.nearest_enemy_position {
-                                                let to_enemy = enemy_pos - transform.translation;
-                                                // Determine cardinal direction
-                                                if to_enemy.x.abs() > to_enemy.z.abs() {
-                                                    if to_enemy.x > 0.0 {
-                                                        TurretDirection::East
-                                                    } else {
-                                                        TurretDirection::West
-                                                    }
-                                                } else {
-                                                    if to_enemy.z > 0.0 {
-                                                        TurretDirection::South
-                                                    } else {
-                                                        TurretDirection::North
-                                                    }
-                                                }
-                                            } else {
-                                                TurretDirection::South
-                                            }
-                                        };
-
-                                        let rotation = turret_dir.to_quat();
-                                        let barrel_offset = -Vec3::Z * 2.5;
-
-                                        let turret_entity = commands
-                                            .spawn((
-                                                Turret {
-                                                    owner: *player_id,
-                                                    direction: turret_dir,
-                                                    last_shot: 0.0,
-                                                },
-                                                Structure {
-                                                    ty: StructureType::Turret,
-                                                    collider_scale: 0.5,
-                                                },
-                                                Mesh3d(meshes.add(Cuboid::new(1.0, 2.0, 1.0))),
-                                                MeshMaterial3d(
-                                                    materials.add(Color::srgb(0.2, 0.2, 0.2)),
-                                                ),
-                                                Transform::from_translation(
-                                                    position + Vec3::Y * 1.0,
-                                                )
-                                                .with_rotation(rotation),
-                                            ))
-                                            .with_children(|parent| {
-                                                parent.spawn((
-                                                    Mesh3d(meshes.add(Cuboid::new(0.4, 0.4, 3.0))),
-                                                    MeshMaterial3d(
-                                                        materials.add(Color::srgb(0.1, 0.1, 0.1)),
-                                                    ),
-                                                    Transform::from_translation(barrel_offset),
-                                                ));
-                                            })
-                                            .id();
-
-                                        grid.occupants.insert((tile_x, tile_y), turret_entity);
-                                        crate::arena::regenerate_nav_graph(
-                                            &config,
-                                            &grid,
-                                            &mut nav_graph,
-                                        );
-                                        match_log.add(GameEvent::StructureBuilt {
-                                            entity: *player_id,
-                                            structure: StructureType::Turret,
-                                            location: (tile_x, tile_y),
-                                            time: time.elapsed_secs(),
-                                        });
-                                        info!(
-                                            "AI Built Turret at ({}, {}) facing {:?}",
-                                            tile_x, tile_y, turret_dir
-                                        );
+                                } else {
+                                    if to_enemy.z > 0.0 {
+                                        TurretDirection::South
+                                    } else {
+                                        TurretDirection::North
                                     }
                                 }
-                                _ => {}
+                            } else {
+                                TurretDirection::South
                             }
-                        }
-                    }
-                    Action::Idle => {
-                        // Do nothing
+                        };
+
+                        let rotation = turret_dir.to_quat();
+
+                        let turret_entity = context
+                            .commands
+                            .spawn((
+                                Turret {
+                                    owner: *player_id,
+                                    direction: turret_dir,
+                                    last_shot: 0.0,
+                                },
+                                Structure {
+                                    ty: StructureType::Turret,
+                                    collider_scale: 0.5,
+                                },
+                                Transform::from_translation(position + Vec3::Y * 1.0)
+                                    .with_rotation(rotation),
+                            ))
+                            .id();
+
+                        context
+                            .grid
+                            .occupants
+                            .insert((tile_x, tile_y), turret_entity);
+                        crate::arena::regenerate_nav_graph(
+                            config,
+                            &context.grid,
+                            &mut context.nav_graph,
+                        );
+                        context.match_log.add(GameEvent::StructureBuilt {
+                            entity: *player_id,
+                            structure: StructureType::Turret,
+                            location: (tile_x, tile_y),
+                            time: context.time.elapsed_secs(),
+                        });
+                        blackboard.record_built(StructureType::Turret);
+                        info!(
+                            "AI Built Turret at ({}, {}) facing {:?}",
+                            tile_x, tile_y, turret_dir
+                        );
+                        return NodeStatus::Success;
                     }
                 }
-                break; // Execute only the highest priority rule
+                _ => {}
             }
+            // Nothing to build with
+            NodeStatus::Failure
+        }
+        Action::Idle => {
+            // Do nothing
+            NodeStatus::Success
+        }
+        Action::SetFlag { flag, value } => {
+            blackboard.set_flag(flag, *value);
+            NodeStatus::Success
+        }
+        Action::IncrementCounter(counter) => {
+            blackboard.increment(counter);
+            NodeStatus::Success
+        }
+        Action::CollectNearest(item) => {
+            let nearest = context
+                .collectible_query
+                .iter()
+                .filter(|(collectible, _)| collectible.ty == *item)
+                .map(|(_, collectible_transform)| collectible_transform.translation)
+                .min_by(|a, b| {
+                    let a = a.distance_squared(transform.translation);
+                    let b = b.distance_squared(transform.translation);
+                    a.total_cmp(&b)
+                });
+            let Some(position) = nearest else {
+                return NodeStatus::Failure;
+            };
+
+            let (x, y) = config.world_to_tile(position);
+            set_target(target, x, y);
+            // `update_inventory` picks it up once we stand on it.
+            if config.world_to_tile(transform.translation) == (x, y) {
+                NodeStatus::Success
+            } else {
+                NodeStatus::Running
+            }
+        }
+        Action::MoveToTile { x, y } => {
+            if *x >= config.width
+                || *y >= config.height
+                || context.grid.occupants.contains_key(&(*x, *y))
+            {
+                return NodeStatus::Failure;
+            }
+            set_target(target, *x, *y);
+            if config.world_to_tile(transform.translation) == (*x, *y) {
+                NodeStatus::Success
+            } else {
+                NodeStatus::Running
+            }
+        }
+        Action::DestroyStructureAt { x, y } => {
+            let Some(&occupant) = context.grid.occupants.get(&(*x, *y)) else {
+                // Already gone.
+                return NodeStatus::Success;
+            };
+            // Arena walls can't be destroyed.
+            let structure = match context.structure_query.get(occupant) {
+                Ok(structure) if structure.ty != StructureType::Wall => structure.ty,
+                _ => return NodeStatus::Failure,
+            };
+
+            let tile_position = config.tile_to_world(*x, *y);
+            if transform.translation.xz().distance(tile_position.xz()) > BUILD_MAX_DISTANCE {
+                // The tile itself is blocked, walk to the free neighbor closest to us.
+                let (my_x, my_y) = config.world_to_tile(transform.translation);
+                let neighbor = [(0, 1), (1, 0), (0, -1), (-1, 0)]
+                    .into_iter()
+                    .filter_map(|(dx, dy)| {
+                        Some((x.checked_add_signed(dx)?, y.checked_add_signed(dy)?))
+                    })
+                    .filter(|&(nx, ny)| {
+                        nx < config.width
+                            && ny < config.height
+                            && !context.grid.occupants.contains_key(&(nx, ny))
+                    })
+                    .min_by_key(|&(nx, ny)| nx.abs_diff(my_x) + ny.abs_diff(my_y));
+                let Some((nx, ny)) = neighbor else {
+                    return NodeStatus::Failure;
+                };
+                set_target(target, nx, ny);
+                return NodeStatus::Running;
+            }
+
+            context.commands.entity(occupant).despawn();
+            context.grid.occupants.remove(&(*x, *y));
+            crate::arena::regenerate_nav_graph(config, &context.grid, &mut context.nav_graph);
+            context.match_log.add(GameEvent::StructureDestroyed {
+                destroyer: Some(*player_id),
+                structure,
+                location: (*x, *y),
+                time: context.time.elapsed_secs(),
+            });
+            info!("AI Destroyed {:?} at ({}, {})", structure, x, y);
+            NodeStatus::Success
+        }
+        Action::Sequence(_) | Action::PatrolAreas(_) | Action::GuardArea { .. } => {
+            unreachable!("compound actions are handled by `execute_step`")
         }
     }
 }
@@ -384,12 +1080,17 @@ fn path_following_system(
             &Transform,
             &mut PathFollower,
             &mut MovementController,
+            Option<&ExternalControl>,
         ),
         With<AiPlayer>,
     >,
     config: Res<ArenaConfig>,
 ) {
-    for (entity, transform, mut follower, mut controller) in query.iter_mut() {
+    for (entity, transform, mut follower, mut controller, external) in query.iter_mut() {
+        if external.is_some_and(|external| external.manual_movement) {
+            continue;
+        }
+
         if follower.current_index >= follower.path.len() {
             controller.input_direction = Vec3::ZERO;
             continue;
@@ -427,3 +1128,474 @@ fn path_following_system(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::rules::Rule;
+    use super::*;
+    use crate::headless::HeadlessMatch;
+
+    fn rule(name: &str, priority: i32, condition: Condition, action: Action) -> Rule {
+        Rule {
+            name: name.to_string(),
+            priority,
+            condition,
+            action,
+            exit_condition: None,
+            min_duration: None,
+            cooldown: None,
+            score: None,
+        }
+    }
+
+    /// Side 0 plays `rules` against an idle side 1.
+    fn game(rules: Vec<Rule>) -> HeadlessMatch {
+        let idle = RuleSet {
+            rules: vec![rule("Idle", 0, Condition::True, Action::Idle)],
+            strategy: Strategy::Priority,
+        };
+        let rules = RuleSet {
+            rules,
+            strategy: Strategy::Priority,
+        };
+        HeadlessMatch::new(7, [rules, idle])
+    }
+
+    fn steps(game: &mut HeadlessMatch, ticks: usize) {
+        for _ in 0..ticks {
+            game.step();
+        }
+    }
+
+    fn ai(game: &mut HeadlessMatch) -> EntityWorldMut<'_> {
+        let entity = game.entities()[0];
+        game.app_mut().world_mut().entity_mut(entity)
+    }
+
+    fn counter(game: &mut HeadlessMatch, name: &str) -> u32 {
+        ai(game).get::<Blackboard>().unwrap().counter(name)
+    }
+
+    fn selected(game: &mut HeadlessMatch) -> Option<String> {
+        let ai = ai(game);
+        let state = ai.get::<RuleEvaluatorState>().unwrap();
+        state.selected.as_ref().map(|(name, _)| name.clone())
+    }
+
+    /// Steps until a rule is selected, within the first few ticks.
+    fn first_selection(game: &mut HeadlessMatch) -> String {
+        for _ in 0..5 {
+            game.step();
+            if let Some(name) = selected(game) {
+                return name;
+            }
+        }
+        panic!("no rule got selected");
+    }
+
+    /// Moves side 1 onto `tile`.
+    fn move_enemy(game: &mut HeadlessMatch, tile: (u32, u32)) {
+        let enemy = game.entities()[1];
+        let world = game.app_mut().world_mut();
+        let tile_size = world.resource::<ArenaConfig>().tile_size;
+        let mut transform = world.get_mut::<Transform>(enemy).unwrap();
+        transform.translation.x = (tile.0 as f32 + 0.5) * tile_size;
+        transform.translation.z = (tile.1 as f32 + 0.5) * tile_size;
+    }
+
+    /// The rule picked when `condition` is checked first, with side 1 standing on `enemy`.
+    fn check(condition: Condition, enemy: (u32, u32)) -> String {
+        let rules = vec![
+            rule("Met", 2, condition, Action::Idle),
+            rule("Unmet", 1, Condition::True, Action::Idle),
+        ];
+        let mut game = game(rules);
+        move_enemy(&mut game, enemy);
+        first_selection(&mut game)
+    }
+
+    #[test]
+    fn enemy_within_tiles_counts_tiles() {
+        // Side 0 spawns two tiles to the west, in sight.
+        assert_eq!(check(Condition::EnemyWithinTiles(3), (6, 2)), "Met");
+        assert_eq!(check(Condition::EnemyWithinTiles(1), (6, 2)), "Unmet");
+    }
+
+    #[test]
+    fn enemy_in_area_checks_the_enemy_tile() {
+        assert_eq!(
+            check(Condition::EnemyInArea("UserBase".into()), (6, 2)),
+            "Met"
+        );
+        assert_eq!(
+            check(Condition::EnemyInArea("EnemyBase".into()), (6, 2)),
+            "Unmet"
+        );
+        // Out of sight, wherever it stands.
+        assert_eq!(
+            check(Condition::EnemyInArea("EnemyBase".into()), (35, 2)),
+            "Unmet"
+        );
+    }
+
+    fn health_at_most(hp: u32) -> Condition {
+        Condition::IsHealthLow { threshold: hp }
+    }
+
+    /// Side 0 starts with 3 hit points.
+    fn set_health(game: &mut HeadlessMatch, hp: u32) {
+        ai(game).get_mut::<Hp>().unwrap().current = hp;
+    }
+
+    fn go_or_rest(go: Rule) -> HeadlessMatch {
+        let rest = rule("Rest", 1, Condition::True, Action::Idle);
+        game(vec![go, rest])
+    }
+
+    #[test]
+    fn min_duration_holds_the_selection() {
+        let mut go = rule("Go", 2, health_at_most(1), Action::Idle);
+        go.min_duration = Some(0.5);
+        let mut game = go_or_rest(go);
+
+        set_health(&mut game, 1);
+        assert_eq!(first_selection(&mut game), "Go");
+        set_health(&mut game, 3);
+        steps(&mut game, 20);
+        assert_eq!(selected(&mut game).as_deref(), Some("Go"));
+        steps(&mut game, 20);
+        assert_eq!(selected(&mut game).as_deref(), Some("Rest"));
+    }
+
+    #[test]
+    fn cooldown_blocks_reselection() {
+        let mut go = rule("Go", 2, health_at_most(1), Action::Idle);
+        go.cooldown = Some(1.0);
+        let mut game = go_or_rest(go);
+
+        set_health(&mut game, 1);
+        assert_eq!(first_selection(&mut game), "Go");
+        set_health(&mut game, 3);
+        game.step();
+        assert_eq!(selected(&mut game).as_deref(), Some("Rest"));
+        set_health(&mut game, 1);
+        steps(&mut game, 50);
+        assert_eq!(selected(&mut game).as_deref(), Some("Rest"));
+        steps(&mut game, 20);
+        assert_eq!(selected(&mut game).as_deref(), Some("Go"));
+    }
+
+    #[test]
+    fn exit_condition_releases_the_selection() {
+        let mut go = rule("Go", 2, health_at_most(1), Action::Idle);
+        go.exit_condition = Some(Condition::Not(Box::new(health_at_most(2))));
+        let mut game = go_or_rest(go);
+
+        set_health(&mut game, 1);
+        assert_eq!(first_selection(&mut game), "Go");
+        // Only the exit condition matters once selected.
+        set_health(&mut game, 2);
+        steps(&mut game, 10);
+        assert_eq!(selected(&mut game).as_deref(), Some("Go"));
+        set_health(&mut game, 3);
+        game.step();
+        assert_eq!(selected(&mut game).as_deref(), Some("Rest"));
+    }
+
+    /// Side 0 picks among `rules` by score.
+    fn utility(rules: Vec<Rule>) -> HeadlessMatch {
+        let mut game = game(rules);
+        ai(&mut game).get_mut::<AiRuleSet>().unwrap().0.strategy = Strategy::Utility;
+        game
+    }
+
+    fn scored(name: &str, priority: i32, score: f32) -> Rule {
+        let mut rule = rule(name, priority, Condition::True, Action::Idle);
+        rule.score = Some(Score::Constant(score));
+        rule
+    }
+
+    #[test]
+    fn utility_selects_the_highest_score() {
+        let mut game = utility(vec![scored("Low", 2, 1.0), scored("High", 1, 2.0)]);
+        assert_eq!(first_selection(&mut game), "High");
+
+        let player = game.players()[0];
+        let logged = game
+            .app()
+            .world()
+            .resource::<MatchLog>()
+            .events
+            .iter()
+            .find_map(|event| match event {
+                GameEvent::AiDecision {
+                    entity,
+                    rule_name,
+                    score,
+                    ..
+                } if *entity == player => Some((rule_name.clone(), *score)),
+                _ => None,
+            });
+        assert_eq!(logged, Some(("High".to_string(), Some(2.0))));
+    }
+
+    #[test]
+    fn utility_ties_go_to_the_higher_priority() {
+        let mut game = utility(vec![scored("Second", 1, 3.0), scored("First", 2, 3.0)]);
+        assert_eq!(first_selection(&mut game), "First");
+    }
+
+    #[test]
+    fn blackboard_action_applies_once_per_selection() {
+        let count = rule(
+            "Count",
+            1,
+            Condition::True,
+            Action::IncrementCounter("count".to_string()),
+        );
+        // Evaluated on every tick, the rule stays selected for the whole second.
+        let mut game = game(vec![count]);
+        steps(&mut game, 60);
+        assert_eq!(counter(&mut game, "count"), 1);
+    }
+
+    fn flag(name: &str) -> Condition {
+        Condition::FlagSet(name.to_string())
+    }
+
+    /// Replaces the rules of side 0, for rules that depend on where it spawned.
+    fn set_rules(game: &mut HeadlessMatch, rules: Vec<Rule>) {
+        ai(game).insert(AiRuleSet(RuleSet {
+            rules,
+            strategy: Strategy::Priority,
+        }));
+    }
+
+    fn set_flag(game: &mut HeadlessMatch, name: &str, value: bool) {
+        let mut ai = ai(game);
+        let mut blackboard = ai
+            .get_mut::<Blackboard>()
+            .expect("AI players have a blackboard");
+        blackboard.set_flag(name, value);
+    }
+
+    fn target(game: &mut HeadlessMatch) -> (u32, u32) {
+        let ai = ai(game);
+        let target = ai.get::<TargetDestination>().unwrap();
+        (target.x, target.y)
+    }
+
+    fn tile(game: &mut HeadlessMatch) -> (u32, u32) {
+        let position = ai(game).get::<Transform>().unwrap().translation;
+        game.app()
+            .world()
+            .resource::<ArenaConfig>()
+            .world_to_tile(position)
+    }
+
+    fn sequence(x: u32, y: u32) -> Action {
+        Action::Sequence(vec![
+            Action::MoveToTile { x, y },
+            Action::SetFlag {
+                flag: "done".to_string(),
+                value: true,
+            },
+        ])
+    }
+
+    #[test]
+    fn sequence_moves_on_after_a_finished_step() {
+        let mut game = game(Vec::new());
+        let (x, y) = tile(&mut game);
+        set_rules(
+            &mut game,
+            vec![rule("Here", 1, Condition::True, sequence(x, y))],
+        );
+        first_selection(&mut game);
+        assert!(ai(&mut game).get::<Blackboard>().unwrap().flag("done"));
+    }
+
+    #[test]
+    fn sequence_waits_for_a_running_step() {
+        // A tile across the arena takes more than a few ticks to reach.
+        let far = rule("Far", 1, Condition::True, sequence(5, 16));
+        let mut game = game(vec![far]);
+        first_selection(&mut game);
+        steps(&mut game, 5);
+        assert_eq!(target(&mut game), (5, 16));
+        assert!(!ai(&mut game).get::<Blackboard>().unwrap().flag("done"));
+    }
+
+    #[test]
+    fn patrol_heads_for_the_next_area_once_in_one() {
+        let patrol = Action::PatrolAreas(vec!["UserBase".into(), "CenterArena".into()]);
+        let mut game = game(vec![rule("Patrol", 1, Condition::True, patrol)]);
+        first_selection(&mut game);
+        // Side 0 spawns in its base.
+        steps(&mut game, 2);
+        assert_eq!(target(&mut game), (20, 13));
+    }
+
+    #[test]
+    fn guard_stays_in_the_area_without_intruders() {
+        let guard = Action::GuardArea {
+            area: "UserBase".into(),
+            radius: 3,
+        };
+        let mut game = game(vec![rule("Guard", 1, Condition::True, guard)]);
+        first_selection(&mut game);
+        assert_eq!(target(&mut game), (5, 5));
+    }
+
+    fn destroyed(game: &HeadlessMatch, location: (u32, u32)) -> bool {
+        let log = game.app().world().resource::<MatchLog>();
+        log.events.iter().any(|event| {
+            matches!(event, GameEvent::StructureDestroyed { location: at, .. } if *at == location)
+        })
+    }
+
+    #[test]
+    fn destroy_removes_obstacles_but_not_walls() {
+        let mut game = game(Vec::new());
+        let (x, y) = tile(&mut game);
+        let world = game.app_mut().world_mut();
+        let obstacle = world
+            .spawn((
+                Obstacle,
+                Structure {
+                    ty: StructureType::Obstacle,
+                    collider_scale: 1.0,
+                },
+                Transform::from_translation(
+                    world.resource::<ArenaConfig>().tile_to_world(x + 1, y),
+                ),
+            ))
+            .id();
+        world
+            .resource_mut::<ArenaGrid>()
+            .occupants
+            .insert((x + 1, y), obstacle);
+
+        let rules = vec![
+            rule(
+                "Obstacle",
+                2,
+                flag("obstacle"),
+                Action::DestroyStructureAt { x: x + 1, y },
+            ),
+            rule(
+                "Wall",
+                1,
+                Condition::True,
+                Action::DestroyStructureAt { x: 0, y: 0 },
+            ),
+        ];
+        set_rules(&mut game, rules);
+        steps(&mut game, 5);
+        assert!(!destroyed(&game, (0, 0)));
+        assert!(game
+            .app()
+            .world()
+            .resource::<ArenaGrid>()
+            .occupants
+            .contains_key(&(0, 0)));
+
+        set_flag(&mut game, "obstacle", true);
+        steps(&mut game, 2);
+        assert!(destroyed(&game, (x + 1, y)));
+        let grid = game.app().world().resource::<ArenaGrid>();
+        assert!(!grid.occupants.contains_key(&(x + 1, y)));
+    }
+
+    #[test]
+    fn counter_counts_selections() {
+        // Each rule counts and hands over to the other one at the next think.
+        let on = rule(
+            "On",
+            2,
+            flag("toggle"),
+            Action::Sequence(vec![
+                Action::IncrementCounter("on".to_string()),
+                Action::SetFlag {
+                    flag: "toggle".to_string(),
+                    value: false,
+                },
+            ]),
+        );
+        let off = rule(
+            "Off",
+            1,
+            Condition::Not(Box::new(flag("toggle"))),
+            Action::Sequence(vec![
+                Action::IncrementCounter("off".to_string()),
+                Action::SetFlag {
+                    flag: "toggle".to_string(),
+                    value: true,
+                },
+            ]),
+        );
+        let mut game = game(vec![on, off]);
+        ai(&mut game).insert(ThinkRate(5.0));
+        steps(&mut game, 120);
+
+        let player = game.players()[0];
+        let selections = game
+            .app()
+            .world()
+            .resource::<MatchLog>()
+            .events
+            .iter()
+            .filter(|event| {
+                matches!(event, GameEvent::AiDecision { entity, condition_met: true, .. }
+                    if *entity == player)
+            })
+            .count() as u32;
+        assert!(selections > 2);
+        let counted = counter(&mut game, "on") + counter(&mut game, "off");
+        assert_eq!(counted, selections);
+    }
+
+    #[test]
+    fn rules_are_reconsidered_at_the_think_rate() {
+        let counted = rule(
+            "Counted",
+            2,
+            Condition::CounterAtLeast {
+                counter: "count".to_string(),
+                value: 1,
+            },
+            Action::Idle,
+        );
+        let count = rule(
+            "Count",
+            1,
+            Condition::True,
+            Action::IncrementCounter("count".to_string()),
+        );
+        let mut game = game(vec![counted, count]);
+        ai(&mut game).insert(ThinkRate(5.0));
+
+        assert_eq!(first_selection(&mut game), "Count");
+        assert_eq!(counter(&mut game, "count"), 1);
+        // "Counted" holds from now on, but the next think is a dozen ticks away.
+        steps(&mut game, 5);
+        assert_eq!(selected(&mut game).as_deref(), Some("Count"));
+        steps(&mut game, 20);
+        assert_eq!(selected(&mut game).as_deref(), Some("Counted"));
+    }
+
+    #[test]
+    fn selected_action_runs_between_thinks() {
+        let mut game = game(Vec::new());
+        let (x, y) = tile(&mut game);
+        // Succeeds at once, since the AI already stands there.
+        let stay = rule("Stay", 1, Condition::True, Action::MoveToTile { x, y });
+        set_rules(&mut game, vec![stay]);
+        ai(&mut game).insert(ThinkRate(5.0));
+        first_selection(&mut game);
+
+        ai(&mut game).insert(TargetDestination { x: x + 1, y });
+        game.step();
+        assert_eq!(target(&mut game), (x, y));
+    }
+}
@@ -0,0 +1,315 @@
+//! Build site selection for `Action::Build`.
+//!
+//! Candidates are the free tiles within `BUILD_MAX_DISTANCE` of the AI. The enemy's way to
+//! the AI is the path from the enemy's tile, or where it was last seen, to the AI's tile.
+//! A tile scores as a choke point by how much longer that way gets once the tile is
+//! blocked, and as a turret spot by how much of it the turret's cone covers. Tiles that
+//! would cut the AI off from the enemy or from its current destination are never picked.
+
+use super::rules::Placement;
+use crate::arena::ArenaConfig;
+use crate::building::{StructureType, BUILD_MAX_DISTANCE};
+use crate::combat::{TurretDirection, TURRET_CONE_COS, TURRET_RANGE};
+use crate::pathfinding::{find_path, NavGraph};
+use bevy::platform::collections::HashSet;
+use std::collections::VecDeque;
+
+const DIRECTIONS: [TurretDirection; 4] = [
+    TurretDirection::North,
+    TurretDirection::East,
+    TurretDirection::South,
+    TurretDirection::West,
+];
+
+/// Extra path steps worth as much as covering the whole way with a turret.
+const DETOUR_SCALE: f32 = 6.0;
+/// Score lost per tile of distance from the AI, so ties go to closer tiles.
+const DISTANCE_PENALTY: f32 = 0.01;
+
+/// What the planner knows about the arena and the AI.
+pub struct Surroundings<'a> {
+    pub config: &'a ArenaConfig,
+    pub nav_graph: &'a NavGraph,
+    /// Tile of the AI.
+    pub from: (u32, u32),
+    /// Tile of the enemy, if visible or remembered.
+    pub enemy: Option<(u32, u32)>,
+    /// Tile the AI is walking to.
+    pub destination: (u32, u32),
+}
+
+/// Where to build, and which way a turret faces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Site {
+    pub tile: (u32, u32),
+    pub direction: Option<TurretDirection>,
+}
+
+/// Picks the site for `structure` according to `placement`. `direction` fixes the facing
+/// of turrets, otherwise the facing covering most of the enemy's way is chosen, or the one
+/// towards the enemy. `Placement::Here` returns the AI's tile and `direction` as they are.
+/// `None` if no tile is of any use, e.g. because the enemy's way is unknown.
+pub fn plan(
+    placement: Placement,
+    structure: StructureType,
+    direction: Option<TurretDirection>,
+    surroundings: &Surroundings,
+) -> Option<Site> {
+    let Surroundings {
+        config,
+        nav_graph,
+        from,
+        enemy,
+        destination,
+    } = *surroundings;
+    if placement == Placement::Here {
+        return Some(Site {
+            tile: from,
+            direction,
+        });
+    }
+    let is_turret = structure == StructureType::Turret;
+
+    let approach = enemy
+        .and_then(|enemy| find_path(enemy, from, nav_graph))
+        .unwrap_or_default();
+    // Without a way to the enemy, turrets face it directly.
+    let facing_for = |tile: (u32, u32)| -> Option<(TurretDirection, f32)> {
+        if !is_turret {
+            return None;
+        }
+        let candidates: &[TurretDirection] = match &direction {
+            Some(direction) => std::slice::from_ref(direction),
+            None => &DIRECTIONS,
+        };
+        candidates
+            .iter()
+            .map(|direction| (*direction, coverage(tile, *direction, &approach, config)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .filter(|(_, covered)| *covered > 0.0 || direction.is_some())
+            .or_else(|| enemy.map(|enemy| (facing(tile, enemy, config), 0.0)))
+    };
+
+    let (choke_weight, coverage_weight) = match placement {
+        Placement::ChokePoint => (1.0, 0.0),
+        Placement::Coverage => (0.0, 1.0),
+        _ => (1.0, 1.0),
+    };
+    let on_approach: HashSet<(u32, u32)> = approach.iter().copied().collect();
+    // Routes that must stay open, with their current length.
+    let enemy_route = enemy.and_then(|enemy| Some((enemy, steps(nav_graph, enemy, from, None)?)));
+    let keeps_destination = steps(nav_graph, from, destination, None).is_some();
+    let origin = config.tile_to_world(from.0, from.1);
+
+    // Sorted, so ties don't depend on hash order.
+    let mut tiles: Vec<(u32, u32)> = nav_graph.nodes.keys().copied().collect();
+    tiles.sort_unstable();
+
+    let mut best: Option<(Site, f32)> = None;
+    for tile in tiles {
+        let distance = config.tile_to_world(tile.0, tile.1).distance(origin);
+        if tile == from || Some(tile) == enemy || distance > BUILD_MAX_DISTANCE {
+            continue;
+        }
+
+        // Never wall ourselves in.
+        if keeps_destination && steps(nav_graph, from, destination, Some(tile)).is_none() {
+            continue;
+        }
+        let mut detour = 0;
+        if let Some((enemy, length)) = enemy_route {
+            match steps(nav_graph, enemy, from, Some(tile)) {
+                Some(blocked_length) => detour = blocked_length.saturating_sub(length),
+                None => continue,
+            }
+        }
+
+        let choke = if on_approach.contains(&tile) {
+            (detour as f32 / DETOUR_SCALE).min(1.0) + narrowness(tile, nav_graph)
+        } else {
+            0.0
+        };
+        let (direction, covered) = match facing_for(tile) {
+            Some((direction, covered)) => (Some(direction), covered),
+            None => (None, 0.0),
+        };
+
+        let value = choke_weight * choke + coverage_weight * covered;
+        if value <= 0.0 {
+            continue;
+        }
+        let score = value - DISTANCE_PENALTY * distance / config.tile_size;
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((Site { tile, direction }, score));
+        }
+    }
+
+    best.map(|(site, _)| site)
+}
+
+/// Share of the tiles of `approach` a turret on `tile` facing `direction` can shoot at.
+fn coverage(
+    tile: (u32, u32),
+    direction: TurretDirection,
+    approach: &[(u32, u32)],
+    config: &ArenaConfig,
+) -> f32 {
+    if approach.is_empty() {
+        return 0.0;
+    }
+    let position = config.tile_to_world(tile.0, tile.1);
+    let forward = direction.to_vec3();
+    let covered = approach
+        .iter()
+        .filter(|(x, y)| {
+            let to_tile = config.tile_to_world(*x, *y) - position;
+            let distance = to_tile.length();
+            distance > 0.0
+                && distance < TURRET_RANGE
+                && to_tile.normalize().dot(forward) > TURRET_CONE_COS
+        })
+        .count();
+    covered as f32 / approach.len() as f32
+}
+
+/// Cardinal direction from `tile` towards `target`.
+fn facing(tile: (u32, u32), target: (u32, u32), config: &ArenaConfig) -> TurretDirection {
+    let to_target = config.tile_to_world(target.0, target.1) - config.tile_to_world(tile.0, tile.1);
+    if to_target.x.abs() > to_target.z.abs() {
+        if to_target.x > 0.0 {
+            TurretDirection::East
+        } else {
+            TurretDirection::West
+        }
+    } else if to_target.z > 0.0 {
+        TurretDirection::South
+    } else {
+        TurretDirection::North
+    }
+}
+
+/// Up to 1 for tiles with few walkable neighbors, like doorways and corridors.
+fn narrowness(tile: (u32, u32), nav_graph: &NavGraph) -> f32 {
+    let neighbors = nav_graph.nodes.get(&tile).map_or(0, Vec::len);
+    (8 - neighbors.min(8)) as f32 / 8.0
+}
+
+/// Length in steps of the shortest way from `start` to `goal` that avoids `blocked`, as if
+/// a structure stood there. `None` if there is no way.
+fn steps(
+    nav_graph: &NavGraph,
+    start: (u32, u32),
+    goal: (u32, u32),
+    blocked: Option<(u32, u32)>,
+) -> Option<u32> {
+    // A structure also blocks the diagonal moves around its corners.
+    let passes = |a: (u32, u32), b: (u32, u32)| {
+        Some(b) != blocked && Some((a.0, b.1)) != blocked && Some((b.0, a.1)) != blocked
+    };
+
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(start);
+    queue.push_back((start, 0));
+
+    while let Some((tile, distance)) = queue.pop_front() {
+        if tile == goal {
+            return Some(distance);
+        }
+        for &neighbor in nav_graph.nodes.get(&tile).into_iter().flatten() {
+            if passes(tile, neighbor) && visited.insert(neighbor) {
+                queue.push_back((neighbor, distance + 1));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::ArenaGrid;
+    use crate::pathfinding::regenerate_nav_graph;
+    use bevy::prelude::Entity;
+
+    const SIZE: u32 = 12;
+
+    /// An open square arena of `SIZE` tiles with structures on `occupied`.
+    fn arena(occupied: &[(u32, u32)]) -> (ArenaConfig, NavGraph) {
+        let config = ArenaConfig {
+            width: SIZE,
+            height: SIZE,
+            tile_size: 4.0,
+        };
+        let mut grid = ArenaGrid::default();
+        for x in 0..SIZE {
+            for y in 0..SIZE {
+                grid.tiles.insert((x, y), Entity::PLACEHOLDER);
+            }
+        }
+        for tile in occupied {
+            grid.occupants.insert(*tile, Entity::PLACEHOLDER);
+        }
+        let mut nav_graph = NavGraph::default();
+        regenerate_nav_graph(&config, &grid, &mut nav_graph);
+        (config, nav_graph)
+    }
+
+    fn plan_turret(config: &ArenaConfig, nav_graph: &NavGraph, from: (u32, u32)) -> Site {
+        let surroundings = Surroundings {
+            config,
+            nav_graph,
+            from,
+            enemy: Some((SIZE - 2, from.1)),
+            destination: from,
+        };
+        plan(
+            Placement::Coverage,
+            StructureType::Turret,
+            None,
+            &surroundings,
+        )
+        .expect("an open arena has room for a turret")
+    }
+
+    #[test]
+    fn occupied_tiles_are_never_picked() {
+        let (config, nav_graph) = arena(&[]);
+        let site = plan_turret(&config, &nav_graph, (2, 6));
+
+        let (config, nav_graph) = arena(&[site.tile]);
+        let replanned = plan_turret(&config, &nav_graph, (2, 6));
+        assert_ne!(replanned.tile, site.tile);
+    }
+
+    #[test]
+    fn sites_are_in_the_arena_and_in_reach() {
+        let (config, nav_graph) = arena(&[]);
+        for from in [(0, 0), (0, SIZE - 1), (1, 6)] {
+            let site = plan_turret(&config, &nav_graph, from);
+            assert!(site.tile.0 < SIZE && site.tile.1 < SIZE);
+            let distance = config
+                .tile_to_world(site.tile.0, site.tile.1)
+                .distance(config.tile_to_world(from.0, from.1));
+            assert!(distance <= BUILD_MAX_DISTANCE, "{:?} is out of reach", site);
+        }
+    }
+
+    #[test]
+    fn plans_dont_depend_on_hash_order() {
+        let (config, nav_graph) = arena(&[(4, 5), (4, 6)]);
+        // Same graph, iterated in another order.
+        let mut resized = NavGraph::default();
+        resized.nodes.reserve(4096);
+        resized.nodes.extend(
+            nav_graph
+                .nodes
+                .iter()
+                .map(|(tile, neighbors)| (*tile, neighbors.clone())),
+        );
+
+        let site = plan_turret(&config, &nav_graph, (2, 6));
+        assert_eq!(plan_turret(&config, &resized, (2, 6)), site);
+        assert_eq!(plan_turret(&config, &nav_graph, (2, 6)), site);
+    }
+}
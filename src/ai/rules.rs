@@ -1,50 +1,445 @@
 use crate::arena::areas::AreaID;
+use crate::arena::CollectibleType;
 use crate::building::StructureType;
 use crate::combat::TurretDirection;
-use serde::{Deserialize, Serialize};
+use bevy::asset::Asset;
+use bevy::reflect::TypePath;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Condition {
     // Primitives
     True,
     IsEnemyVisible,
-    IsHealthLow { threshold: u32 },
+    IsHealthLow {
+        threshold: u32,
+    },
     InArea(AreaID),
-    HasItem { item: String, count: u32 }, // "obstacle", "turret"
+    /// Compares the inventory count of `item` with `count`.
+    HasItem {
+        #[serde(deserialize_with = "deserialize_item")]
+        item: CollectibleType,
+        #[serde(default)]
+        op: Comparison,
+        count: u32,
+    },
     IsUnderAttack,
 
+    // Spatial, distances in tiles
+    /// The nearest visible enemy is at most this far away.
+    EnemyWithinTiles(u32),
+    /// The path to the area is shorter than `tiles`.
+    AreaDistanceBelow {
+        area: AreaID,
+        tiles: u32,
+    },
+    /// The nearest visible enemy stands in the area.
+    EnemyInArea(AreaID),
+    /// The area can be seen from the current position.
+    AreaVisible(AreaID),
+    /// A structure of this type, of any owner, is within `radius`.
+    StructureNearby {
+        ty: StructureType,
+        radius: u32,
+    },
+
+    // Memory, see `Blackboard`
+    FlagSet(String),
+    CounterAtLeast {
+        counter: String,
+        value: u32,
+    },
+
     // Composites
     And(Vec<Condition>),
     Or(Vec<Condition>),
     Not(Box<Condition>),
 }
 
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Comparison {
+    #[default]
+    AtLeast,
+    AtMost,
+    Equal,
+}
+
+impl Comparison {
+    pub fn matches(&self, value: u32, reference: u32) -> bool {
+        match self {
+            Comparison::AtLeast => value >= reference,
+            Comparison::AtMost => value <= reference,
+            Comparison::Equal => value == reference,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::AtLeast => write!(f, ">="),
+            Comparison::AtMost => write!(f, "<="),
+            Comparison::Equal => write!(f, "=="),
+        }
+    }
+}
+
+/// Inventory items a `HasItem` condition can refer to, with their wire names.
+pub const ITEMS: &[(CollectibleType, &str)] = &[
+    (CollectibleType::Obstacle, "Obstacle"),
+    (CollectibleType::Turret, "Turret"),
+];
+
+/// Prefix of the error for unknown `HasItem` items, see `ProtocolError::UnknownItem`.
+pub const UNKNOWN_ITEM_ERROR: &str = "unknown item `";
+
+/// Accepts the wire names in `ITEMS`, ignoring case so older lowercase rule files still load.
+fn deserialize_item<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<CollectibleType, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    ITEMS
+        .iter()
+        .find(|(_, item_name)| item_name.eq_ignore_ascii_case(&name))
+        .map(|(item, _)| *item)
+        .ok_or_else(|| {
+            let expected: Vec<&str> = ITEMS.iter().map(|(_, item_name)| *item_name).collect();
+            de::Error::custom(format!(
+                "{}{}`, expected one of: {}",
+                UNKNOWN_ITEM_ERROR,
+                name,
+                expected.join(", ")
+            ))
+        })
+}
+
+/// Utility of a rule under `Strategy::Utility`, computed from the status of the AI.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Score {
+    Constant(f32),
+    /// Tiles to the nearest visible enemy, or the arena diagonal if none is visible.
+    EnemyDistance,
+    /// Current HP divided by max HP.
+    HpRatio,
+    ItemCount {
+        #[serde(deserialize_with = "deserialize_item")]
+        item: CollectibleType,
+    },
+    VisibleEnemies,
+    /// Sum, 0 when empty.
+    Add(Vec<Score>),
+    /// Product, 1 when empty.
+    Mul(Vec<Score>),
+    /// Smallest value, 0 when empty.
+    Min(Vec<Score>),
+    /// Largest value, 0 when empty.
+    Max(Vec<Score>),
+    If {
+        condition: Condition,
+        then: Box<Score>,
+        otherwise: Box<Score>,
+    },
+}
+
+/// How `rule_evaluation_system` picks the rule to execute.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Strategy {
+    /// The highest priority rule whose condition holds.
+    #[default]
+    Priority,
+    /// The rule with the highest `score` among those whose condition holds. Rules without
+    /// a score use their priority.
+    Utility,
+}
+
+/// Where an `Action::Build` puts its structure, see `placement::plan`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Placement {
+    /// The tile the AI stands on.
+    #[default]
+    Here,
+    /// A narrow spot on the enemy's way to the AI, to make the enemy detour.
+    ChokePoint,
+    /// A turret spot whose cone covers as much of the enemy's way to the AI as possible.
+    Coverage,
+    /// Weighs choke points and coverage together.
+    Auto,
+}
+
+impl Placement {
+    pub const ALL: &'static [Placement] = &[
+        Placement::Here,
+        Placement::ChokePoint,
+        Placement::Coverage,
+        Placement::Auto,
+    ];
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Action {
     MoveToArea(AreaID),
     ChaseEnemy,
     Flee,
+    /// Builds within building range. Turrets without a `direction` face the enemy.
     Build {
         structure: StructureType,
         direction: Option<TurretDirection>,
+        #[serde(default)]
+        placement: Placement,
     },
     Idle,
+    /// Sets or clears a `Blackboard` flag, see `Action::writes_blackboard_only`.
+    SetFlag {
+        flag: String,
+        value: bool,
+    },
+    /// Adds one to a `Blackboard` counter. A rule counts how often it got selected rather than
+    /// for how many ticks, see `Action::writes_blackboard_only`.
+    IncrementCounter(String),
+    /// Walks to the nearest collectible of this type until standing on it.
+    CollectNearest(#[serde(deserialize_with = "deserialize_item")] CollectibleType),
+    /// Visits the areas in order, over and over.
+    PatrolAreas(Vec<AreaID>),
+    /// Chases enemies within `radius` tiles of the area center, returns to it otherwise.
+    GuardArea {
+        area: AreaID,
+        radius: u32,
+    },
+    /// Walks within building range of the tile and removes the obstacle or turret on it.
+    DestroyStructureAt {
+        x: u32,
+        y: u32,
+    },
+    MoveToTile {
+        x: u32,
+        y: u32,
+    },
+    /// Executes the steps in order, each until it succeeds, over as many ticks as needed.
+    /// Starts over after the last step succeeded or any step failed.
+    Sequence(Vec<Action>),
+}
+
+impl Condition {
+    /// Wire names of all variants, advertised to the server during the handshake.
+    pub const VARIANTS: &'static [&'static str] = &[
+        "True",
+        "IsEnemyVisible",
+        "IsHealthLow",
+        "InArea",
+        "HasItem",
+        "IsUnderAttack",
+        "EnemyWithinTiles",
+        "AreaDistanceBelow",
+        "EnemyInArea",
+        "AreaVisible",
+        "StructureNearby",
+        "FlagSet",
+        "CounterAtLeast",
+        "And",
+        "Or",
+        "Not",
+    ];
+
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            Condition::True => "True",
+            Condition::IsEnemyVisible => "IsEnemyVisible",
+            Condition::IsHealthLow { .. } => "IsHealthLow",
+            Condition::InArea(_) => "InArea",
+            Condition::HasItem { .. } => "HasItem",
+            Condition::IsUnderAttack => "IsUnderAttack",
+            Condition::EnemyWithinTiles(_) => "EnemyWithinTiles",
+            Condition::AreaDistanceBelow { .. } => "AreaDistanceBelow",
+            Condition::EnemyInArea(_) => "EnemyInArea",
+            Condition::AreaVisible(_) => "AreaVisible",
+            Condition::StructureNearby { .. } => "StructureNearby",
+            Condition::FlagSet(_) => "FlagSet",
+            Condition::CounterAtLeast { .. } => "CounterAtLeast",
+            Condition::And(_) => "And",
+            Condition::Or(_) => "Or",
+            Condition::Not(_) => "Not",
+        }
+    }
+
+    /// Calls `f` on this condition and on every nested one.
+    pub fn visit(&self, f: &mut impl FnMut(&Condition)) {
+        f(self);
+        match self {
+            Condition::And(conditions) | Condition::Or(conditions) => {
+                for condition in conditions {
+                    condition.visit(f);
+                }
+            }
+            Condition::Not(condition) => condition.visit(f),
+            _ => {}
+        }
+    }
+}
+
+impl Score {
+    /// Wire names of all variants, advertised to the server during the handshake.
+    pub const VARIANTS: &'static [&'static str] = &[
+        "Constant",
+        "EnemyDistance",
+        "HpRatio",
+        "ItemCount",
+        "VisibleEnemies",
+        "Add",
+        "Mul",
+        "Min",
+        "Max",
+        "If",
+    ];
+
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            Score::Constant(_) => "Constant",
+            Score::EnemyDistance => "EnemyDistance",
+            Score::HpRatio => "HpRatio",
+            Score::ItemCount { .. } => "ItemCount",
+            Score::VisibleEnemies => "VisibleEnemies",
+            Score::Add(_) => "Add",
+            Score::Mul(_) => "Mul",
+            Score::Min(_) => "Min",
+            Score::Max(_) => "Max",
+            Score::If { .. } => "If",
+        }
+    }
+
+    /// Calls `f` on this score and on every nested one.
+    pub fn visit(&self, f: &mut impl FnMut(&Score)) {
+        f(self);
+        match self {
+            Score::Add(scores) | Score::Mul(scores) | Score::Min(scores) | Score::Max(scores) => {
+                for score in scores {
+                    score.visit(f);
+                }
+            }
+            Score::If {
+                then, otherwise, ..
+            } => {
+                then.visit(f);
+                otherwise.visit(f);
+            }
+            _ => {}
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Action {
+    /// Wire names of all variants, advertised to the server during the handshake.
+    pub const VARIANTS: &'static [&'static str] = &[
+        "MoveToArea",
+        "ChaseEnemy",
+        "Flee",
+        "Build",
+        "Idle",
+        "SetFlag",
+        "IncrementCounter",
+        "CollectNearest",
+        "PatrolAreas",
+        "GuardArea",
+        "DestroyStructureAt",
+        "MoveToTile",
+        "Sequence",
+    ];
+
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            Action::MoveToArea(_) => "MoveToArea",
+            Action::ChaseEnemy => "ChaseEnemy",
+            Action::Flee => "Flee",
+            Action::Build { .. } => "Build",
+            Action::Idle => "Idle",
+            Action::SetFlag { .. } => "SetFlag",
+            Action::IncrementCounter(_) => "IncrementCounter",
+            Action::CollectNearest(_) => "CollectNearest",
+            Action::PatrolAreas(_) => "PatrolAreas",
+            Action::GuardArea { .. } => "GuardArea",
+            Action::DestroyStructureAt { .. } => "DestroyStructureAt",
+            Action::MoveToTile { .. } => "MoveToTile",
+            Action::Sequence(_) => "Sequence",
+        }
+    }
+
+    /// Whether the action does nothing but write the `Blackboard`, possibly in a `Sequence`.
+    /// Rules apply such actions once per selection rather than on every tick.
+    pub fn writes_blackboard_only(&self) -> bool {
+        match self {
+            Action::SetFlag { .. } | Action::IncrementCounter(_) => true,
+            Action::Sequence(steps) => steps.iter().all(Action::writes_blackboard_only),
+            _ => false,
+        }
+    }
+
+    /// Calls `f` on this action and on every step of a `Sequence`.
+    pub fn visit(&self, f: &mut impl FnMut(&Action)) {
+        f(self);
+        if let Action::Sequence(steps) = self {
+            for step in steps {
+                step.visit(f);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Rule {
     pub name: String,
     pub priority: i32,
+    /// Condition to become the selected rule.
     pub condition: Condition,
     pub action: Action,
+    /// Once selected, the rule stays selected until this holds instead of until `condition`
+    /// stops holding.
+    #[serde(default)]
+    pub exit_condition: Option<Condition>,
+    /// Seconds the rule stays selected after being picked, whatever its conditions say.
+    /// Under `Strategy::Priority`, higher priority rules can still take over.
+    #[serde(default)]
+    pub min_duration: Option<f32>,
+    /// Seconds after the rule stops being selected before it can be picked again.
+    #[serde(default)]
+    pub cooldown: Option<f32>,
+    /// Utility under `Strategy::Utility`, ignored otherwise.
+    #[serde(default)]
+    pub score: Option<Score>,
+}
+
+impl Rule {
+    /// Calls `f` on every condition of the rule, nested ones and those in `score` included.
+    pub fn visit_conditions(&self, f: &mut impl FnMut(&Condition)) {
+        self.condition.visit(f);
+        if let Some(exit_condition) = &self.exit_condition {
+            exit_condition.visit(f);
+        }
+        if let Some(score) = &self.score {
+            score.visit(&mut |score| {
+                if let Score::If { condition, .. } = score {
+                    condition.visit(f);
+                }
+            });
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Also an asset, see `assets::RuleSetLoader`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Asset, TypePath)]
 pub struct RuleSet {
     pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub strategy: Strategy,
 }
 
 impl RuleSet {
+    pub fn empty() -> Self {
+        Self {
+            rules: Vec::new(),
+            strategy: Strategy::Priority,
+        }
+    }
+
     pub fn new_turret_only() -> Self {
         Self {
             rules: vec![
@@ -53,15 +448,22 @@ impl RuleSet {
                     name: "DebugBuildTurret".to_string(),
                     priority: 100,
                     condition: Condition::HasItem {
-                        item: "turret".to_string(),
+                        item: CollectibleType::Turret,
+                        op: Comparison::AtLeast,
                         count: 1,
                     },
                     action: Action::Build {
                         structure: StructureType::Turret,
                         direction: None,
+                        placement: Placement::Here,
                     },
+                    exit_condition: None,
+                    min_duration: None,
+                    cooldown: None,
+                    score: None,
                 },
             ],
+            strategy: Strategy::Priority,
         }
     }
 }
@@ -76,6 +478,10 @@ impl Default for RuleSet {
                     priority: 100,
                     condition: Condition::IsHealthLow { threshold: 1 },
                     action: Action::MoveToArea(AreaID("EnemyBase".to_string())),
+                    exit_condition: None,
+                    min_duration: None,
+                    cooldown: None,
+                    score: None,
                 },
                 // Rule 2: Deploy Combat Turret (High Priority - must be higher than EngageEnemy)
                 Rule {
@@ -84,14 +490,20 @@ impl Default for RuleSet {
                     condition: Condition::And(vec![
                         Condition::IsEnemyVisible,
                         Condition::HasItem {
-                            item: "turret".to_string(),
+                            item: CollectibleType::Turret,
+                            op: Comparison::AtLeast,
                             count: 1,
                         },
                     ]),
                     action: Action::Build {
                         structure: StructureType::Turret,
                         direction: None,
+                        placement: Placement::Here,
                     },
+                    exit_condition: None,
+                    min_duration: None,
+                    cooldown: None,
+                    score: None,
                 },
                 // Rule 3: Engage Enemy (Medium-High Priority)
                 Rule {
@@ -99,6 +511,10 @@ impl Default for RuleSet {
                     priority: 80,
                     condition: Condition::IsEnemyVisible,
                     action: Action::ChaseEnemy,
+                    exit_condition: None,
+                    min_duration: None,
+                    cooldown: None,
+                    score: None,
                 },
                 // Rule 4: Fortify Center (Medium Priority)
                 Rule {
@@ -107,14 +523,20 @@ impl Default for RuleSet {
                     condition: Condition::And(vec![
                         Condition::InArea(AreaID("CenterArena".to_string())),
                         Condition::HasItem {
-                            item: "obstacle".to_string(),
+                            item: CollectibleType::Obstacle,
+                            op: Comparison::AtLeast,
                             count: 1,
                         },
                     ]),
                     action: Action::Build {
                         structure: StructureType::Obstacle,
                         direction: None,
+                        placement: Placement::Here,
                     },
+                    exit_condition: None,
+                    min_duration: None,
+                    cooldown: None,
+                    score: None,
                 },
                 // Rule 5: Claim Center (Low Priority)
                 Rule {
@@ -124,6 +546,10 @@ impl Default for RuleSet {
                         "CenterArena".to_string(),
                     )))),
                     action: Action::MoveToArea(AreaID("CenterArena".to_string())),
+                    exit_condition: None,
+                    min_duration: None,
+                    cooldown: None,
+                    score: None,
                 },
                 // Rule 6: Invade Player Base (Lowest Priority)
                 Rule {
@@ -131,8 +557,47 @@ impl Default for RuleSet {
                     priority: 10,
                     condition: Condition::InArea(AreaID("CenterArena".to_string())),
                     action: Action::MoveToArea(AreaID("UserBase".to_string())),
+                    exit_condition: None,
+                    min_duration: None,
+                    cooldown: None,
+                    score: None,
                 },
             ],
+            strategy: Strategy::Priority,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether 1, 2 and 3 match a reference of 2.
+    fn around_two(op: Comparison) -> [bool; 3] {
+        [op.matches(1, 2), op.matches(2, 2), op.matches(3, 2)]
+    }
+
+    #[test]
+    fn comparisons_include_the_reference() {
+        assert_eq!(around_two(Comparison::AtLeast), [false, true, true]);
+        assert_eq!(around_two(Comparison::AtMost), [true, true, false]);
+        assert_eq!(around_two(Comparison::Equal), [false, true, false]);
+
+        // An empty inventory.
+        assert!(Comparison::AtLeast.matches(0, 0));
+        assert!(Comparison::AtMost.matches(0, 0));
+        assert!(!Comparison::AtLeast.matches(0, 1));
+    }
+
+    #[test]
+    fn has_item_defaults_to_at_least() {
+        let condition: Condition =
+            serde_json::from_str(r#"{ "HasItem": { "item": "turret", "count": 2 } }"#).unwrap();
+        let expected = Condition::HasItem {
+            item: CollectibleType::Turret,
+            op: Comparison::AtLeast,
+            count: 2,
+        };
+        assert_eq!(condition, expected);
+    }
+}
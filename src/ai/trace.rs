@@ -0,0 +1,331 @@
+//! Explanations of rule evaluations, for finding out why an AI did what it did.
+//!
+//! While `DecisionTracing` is on, `rule_evaluation_system` records a `DecisionTrace` every
+//! time an AI thinks: every rule it checked, how, and for conditions the result of every
+//! part together with the value the part looked at.
+
+use super::rules::Condition;
+use super::{evaluate_condition, item_count, ConditionContext};
+use crate::arena::areas::AreaID;
+use crate::player_id::PlayerID;
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecisionTracing {
+    #[default]
+    Off,
+    /// Keeps the recent traces of every AI in `DecisionTraces`.
+    Store,
+    /// Also attaches the `RuleTrace` of a rule to its `GameEvent::AiDecision`s.
+    Attach,
+}
+
+/// `DecisionTrace`s kept per AI before the oldest are dropped.
+pub const TRACES_PER_AI: usize = 64;
+
+/// Recent `DecisionTrace`s of every AI, oldest first.
+#[derive(Resource, Default, Debug)]
+pub struct DecisionTraces(pub HashMap<PlayerID, VecDeque<DecisionTrace>>);
+
+impl DecisionTraces {
+    /// Adds the newest trace of `player`, dropping its oldest beyond `TRACES_PER_AI`.
+    pub fn push(&mut self, player: PlayerID, trace: DecisionTrace) {
+        let traces = self.0.entry(player).or_default();
+        if traces.len() >= TRACES_PER_AI {
+            traces.pop_front();
+        }
+        traces.push_back(trace);
+    }
+
+    /// What `player` thought last time.
+    pub fn latest(&self, player: &PlayerID) -> Option<&DecisionTrace> {
+        self.0.get(player).and_then(VecDeque::back)
+    }
+}
+
+/// Result of a condition and of its parts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ConditionTrace {
+    /// A condition without parts. `value` is what it looked at, e.g. the item count for
+    /// `HasItem` or the distance to the enemy in tiles for `EnemyWithinTiles`.
+    Leaf {
+        condition: Condition,
+        result: bool,
+        value: Option<f32>,
+    },
+    /// Unlike in normal evaluation, inputs after the deciding one are evaluated too.
+    And {
+        inputs: Vec<ConditionTrace>,
+        result: bool,
+    },
+    Or {
+        inputs: Vec<ConditionTrace>,
+        result: bool,
+    },
+    Not {
+        input: Box<ConditionTrace>,
+        result: bool,
+    },
+}
+
+impl ConditionTrace {
+    /// Evaluates `condition` like `evaluate_condition`, keeping the results of all parts.
+    pub fn evaluate(condition: &Condition, context: &ConditionContext) -> Self {
+        let inputs = |conditions: &[Condition]| -> Vec<ConditionTrace> {
+            conditions
+                .iter()
+                .map(|condition| ConditionTrace::evaluate(condition, context))
+                .collect()
+        };
+        match condition {
+            Condition::And(conditions) => {
+                let inputs = inputs(conditions);
+                let result = inputs.iter().all(ConditionTrace::result);
+                ConditionTrace::And { inputs, result }
+            }
+            Condition::Or(conditions) => {
+                let inputs = inputs(conditions);
+                let result = inputs.iter().any(ConditionTrace::result);
+                ConditionTrace::Or { inputs, result }
+            }
+            Condition::Not(condition) => {
+                let input = ConditionTrace::evaluate(condition, context);
+                let result = !input.result();
+                ConditionTrace::Not {
+                    input: Box::new(input),
+                    result,
+                }
+            }
+            _ => ConditionTrace::Leaf {
+                condition: condition.clone(),
+                result: evaluate_condition(condition, context),
+                value: observed_value(condition, context),
+            },
+        }
+    }
+
+    pub fn result(&self) -> bool {
+        match self {
+            ConditionTrace::Leaf { result, .. }
+            | ConditionTrace::And { result, .. }
+            | ConditionTrace::Or { result, .. }
+            | ConditionTrace::Not { result, .. } => *result,
+        }
+    }
+}
+
+/// The number a primitive condition compares, if it compares one.
+fn observed_value(condition: &Condition, context: &ConditionContext) -> Option<f32> {
+    let status = context.status;
+    let tile_size = context.config.tile_size;
+    match condition {
+        Condition::IsEnemyVisible => Some(status.visible_players.len() as f32),
+        Condition::IsHealthLow { .. } => Some(context.hp.current as f32),
+        Condition::HasItem { item, .. } => Some(item_count(context.inventory, *item) as f32),
+        // Seconds since the last hit.
+        Condition::IsUnderAttack => context.damage.last_hit().map(|hit| context.time - hit.time),
+        Condition::EnemyWithinTiles(_) => status
+            .nearest_enemy_position
+            .map(|_| status.nearest_enemy_dist / tile_size),
+        Condition::AreaDistanceBelow { area, .. } => status
+            .area_distances
+            .get(area)
+            .map(|distance| *distance as f32),
+        // Distance to the nearest structure of the type, in tiles.
+        Condition::StructureNearby { ty, .. } => context
+            .structures
+            .iter()
+            .filter(|(structure_ty, _)| structure_ty == ty)
+            .map(|(_, position)| position.xz().distance(context.position.xz()) / tile_size)
+            .reduce(f32::min),
+        Condition::CounterAtLeast { counter, .. } => {
+            Some(context.blackboard.counter(counter) as f32)
+        }
+        _ => None,
+    }
+}
+
+/// How a rule was checked.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Check {
+    /// Kept by its `min_duration`, without evaluating anything.
+    Committed,
+    /// Skipped until its `cooldown` is over.
+    Cooldown,
+    Condition(ConditionTrace),
+    /// The `exit_condition` of the selected rule, which stays selected while it is false.
+    ExitCondition(ConditionTrace),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RuleTrace {
+    pub rule: String,
+    pub check: Check,
+    pub condition_met: bool,
+    /// Utility of the rule under `Strategy::Utility`, if its condition holds.
+    pub score: Option<f32>,
+}
+
+/// What the AI knew about itself when it thought.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Observations {
+    pub hp: u32,
+    pub max_hp: u32,
+    pub obstacles: u32,
+    pub turrets: u32,
+    pub visible_enemies: usize,
+    /// Distance to the nearest visible enemy, in tiles.
+    pub enemy_distance: Option<f32>,
+    pub current_area: Option<AreaID>,
+}
+
+impl Observations {
+    pub fn new(context: &ConditionContext) -> Self {
+        let status = context.status;
+        Observations {
+            hp: context.hp.current,
+            max_hp: context.hp.max,
+            obstacles: context.inventory.obstacles,
+            turrets: context.inventory.turrets,
+            visible_enemies: status.visible_players.len(),
+            enemy_distance: status
+                .nearest_enemy_position
+                .map(|_| status.nearest_enemy_dist / context.config.tile_size),
+            current_area: status.current_area_id.clone(),
+        }
+    }
+}
+
+/// One evaluation of the rules of an AI.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DecisionTrace {
+    pub time: f32,
+    pub observations: Observations,
+    /// Rules in evaluation order, up to the one that ended the evaluation.
+    pub rules: Vec<RuleTrace>,
+    pub selected: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::blackboard::Blackboard;
+    use crate::ai::rules::Comparison;
+    use crate::arena::areas::AreaMap;
+    use crate::arena::{ArenaConfig, CollectibleType};
+    use crate::combat::{DamageHistory, Hp};
+    use crate::player::{Inventory, PlayerStatus};
+
+    /// Traces `condition` for an AI with two turrets and nobody in sight.
+    fn trace(condition: &Condition) -> ConditionTrace {
+        let config = ArenaConfig {
+            width: 10,
+            height: 10,
+            tile_size: 4.0,
+        };
+        let context = ConditionContext {
+            status: &PlayerStatus::default(),
+            hp: &Hp::new(3),
+            inventory: &Inventory {
+                obstacles: 0,
+                turrets: 2,
+            },
+            damage: &DamageHistory::default(),
+            position: Vec3::ZERO,
+            config: &config,
+            area_map: &AreaMap::default(),
+            structures: &[],
+            blackboard: &Blackboard::default(),
+            time: 0.0,
+            under_attack_window: 3.0,
+        };
+        ConditionTrace::evaluate(condition, &context)
+    }
+
+    fn leaf(condition: Condition, result: bool, value: Option<f32>) -> ConditionTrace {
+        ConditionTrace::Leaf {
+            condition,
+            result,
+            value,
+        }
+    }
+
+    #[test]
+    fn and_traces_every_input() {
+        let many_turrets = Condition::HasItem {
+            item: CollectibleType::Turret,
+            op: Comparison::AtLeast,
+            count: 5,
+        };
+        let condition = Condition::And(vec![many_turrets.clone(), Condition::IsEnemyVisible]);
+        let expected = ConditionTrace::And {
+            inputs: vec![
+                leaf(many_turrets, false, Some(2.0)),
+                // Evaluated although the first input already decided.
+                leaf(Condition::IsEnemyVisible, false, Some(0.0)),
+            ],
+            result: false,
+        };
+        assert_eq!(trace(&condition), expected);
+    }
+
+    #[test]
+    fn or_and_not_keep_their_inputs() {
+        let counted = Condition::CounterAtLeast {
+            counter: "count".to_string(),
+            value: 1,
+        };
+        let flag = Condition::FlagSet("flag".to_string());
+        let condition = Condition::Or(vec![
+            flag.clone(),
+            Condition::Not(Box::new(counted.clone())),
+        ]);
+        let expected = ConditionTrace::Or {
+            inputs: vec![
+                leaf(flag, false, None),
+                ConditionTrace::Not {
+                    input: Box::new(leaf(counted, false, Some(0.0))),
+                    result: true,
+                },
+            ],
+            result: true,
+        };
+        assert_eq!(trace(&condition), expected);
+    }
+
+    fn decision(time: f32) -> DecisionTrace {
+        DecisionTrace {
+            time,
+            observations: Observations {
+                hp: 3,
+                max_hp: 3,
+                obstacles: 0,
+                turrets: 0,
+                visible_enemies: 0,
+                enemy_distance: None,
+                current_area: None,
+            },
+            rules: Vec::new(),
+            selected: None,
+        }
+    }
+
+    #[test]
+    fn traces_are_kept_per_ai_up_to_the_limit() {
+        let mut traces = DecisionTraces::default();
+        for time in 0..=TRACES_PER_AI {
+            traces.push(PlayerID(1), decision(time as f32));
+        }
+        traces.push(PlayerID(2), decision(0.0));
+
+        let kept = &traces.0[&PlayerID(1)];
+        assert_eq!(kept.len(), TRACES_PER_AI);
+        assert_eq!(kept.front().unwrap().time, 1.0);
+        let latest = traces.latest(&PlayerID(1)).unwrap();
+        assert_eq!(latest.time, TRACES_PER_AI as f32);
+        assert_eq!(traces.0[&PlayerID(2)].len(), 1);
+    }
+}
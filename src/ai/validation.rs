@@ -0,0 +1,483 @@
+//! Static checks for rule sets and behavior trees, run before they replace the ones of an AI.
+
+use super::behavior::{BehaviorTree, Node};
+use super::rules::{Action, Condition, RuleSet, Score, Strategy};
+use crate::arena::areas::{AreaID, AreaMap};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Suspicious, but the rule set still runs as written.
+    Warning,
+    /// The rule set refers to things that don't exist and should not be used.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IssueKind {
+    UnknownArea(AreaID),
+    /// Target of a movement action that is no neighbor of any other area in the `AreaMap`.
+    /// Only area links are checked, not the `NavGraph`: a connected area can still be out of
+    /// reach behind structures or through tile-level dead ends. Being a heuristic, it's only a
+    /// warning.
+    DisconnectedArea(AreaID),
+    /// An earlier rule with `Condition::True` and no cooldown always matches first.
+    Shadowed {
+        by: String,
+    },
+    DuplicatePriority {
+        priority: i32,
+        other: String,
+    },
+    /// An earlier rule has the same name. The AI tells rules apart by name, see
+    /// `RuleEvaluatorState`, so both would share one selection and cooldown.
+    DuplicateName,
+    /// `And([])` and `Sequence([])` always succeed, `Or([])` and `Selector([])` always fail.
+    /// `PatrolAreas([])` fails as well.
+    EmptyComposite(&'static str),
+    /// `min_duration` or `cooldown` below zero, which behaves like no value.
+    NegativeDuration(&'static str),
+    /// A `min_duration`, `cooldown` or `Score::Constant` that is infinite or NaN. It can't be
+    /// written in the text syntax, see `dsl::print`.
+    NonFiniteNumber(&'static str),
+    /// A `Parallel` node needing more successes than it has children, so it always fails.
+    UnreachableThreshold {
+        threshold: usize,
+        children: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleIssue {
+    pub severity: Severity,
+    /// Name of the offending rule, or `node N` in a behavior tree.
+    pub rule: String,
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for RuleIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: `{}`: ", severity, self.rule)?;
+        match &self.kind {
+            IssueKind::UnknownArea(area) => write!(f, "unknown area `{}`", area.0),
+            IssueKind::DisconnectedArea(area) => write!(
+                f,
+                "target area `{}` is not connected to any other area",
+                area.0
+            ),
+            IssueKind::Shadowed { by } => {
+                write!(f, "never fires, `{}` always matches first", by)
+            }
+            IssueKind::DuplicatePriority { priority, other } => write!(
+                f,
+                "priority {} is also used by `{}`, order depends on the file",
+                priority, other
+            ),
+            IssueKind::DuplicateName => write!(f, "another rule has the same name"),
+            IssueKind::EmptyComposite(name) => write!(f, "empty {}", name),
+            IssueKind::NegativeDuration(field) => write!(f, "{} is negative", field),
+            IssueKind::NonFiniteNumber(field) => write!(f, "{} is not a finite number", field),
+            IssueKind::UnreachableThreshold {
+                threshold,
+                children,
+            } => write!(
+                f,
+                "Parallel needs {} successes but has {} children",
+                threshold, children
+            ),
+        }
+    }
+}
+
+impl RuleSet {
+    /// Reports problems with this rule set on the arena described by `area_map`, whose
+    /// connectivity must already be computed. Issues are sorted by rule evaluation order.
+    pub fn validate(&self, area_map: &AreaMap) -> Vec<RuleIssue> {
+        let mut issues = Vec::new();
+
+        // Same order as `rule_evaluation_system`: stable sort by descending priority.
+        let mut sorted_rules: Vec<_> = self.rules.iter().collect();
+        sorted_rules.sort_by(|a, b| b.priority.cmp(&a.priority));
+
+        let mut always_matches: Option<&str> = None;
+        let mut priorities: HashMap<i32, &str> = HashMap::new();
+        let mut names = HashSet::new();
+
+        for rule in sorted_rules {
+            let mut issue = |severity, kind| {
+                issues.push(RuleIssue {
+                    severity,
+                    rule: rule.name.clone(),
+                    kind,
+                })
+            };
+
+            if let Some(by) = always_matches {
+                issue(
+                    Severity::Warning,
+                    IssueKind::Shadowed { by: by.to_string() },
+                );
+            }
+            // A rule on cooldown is skipped, so only rules without one always match. Under
+            // `Strategy::Utility` scores decide, so nothing is shadowed.
+            let always = self.strategy == Strategy::Priority
+                && rule.condition == Condition::True
+                && rule.cooldown.is_none();
+            if always && always_matches.is_none() {
+                always_matches = Some(&rule.name);
+            }
+
+            if let Some(other) = priorities.insert(rule.priority, &rule.name) {
+                issue(
+                    Severity::Warning,
+                    IssueKind::DuplicatePriority {
+                        priority: rule.priority,
+                        other: other.to_string(),
+                    },
+                );
+            }
+            if !names.insert(&rule.name) {
+                issue(Severity::Error, IssueKind::DuplicateName);
+            }
+
+            rule.visit_conditions(&mut |condition| {
+                check_condition(condition, area_map, &mut issue)
+            });
+
+            let durations = [
+                ("min_duration", rule.min_duration),
+                ("cooldown", rule.cooldown),
+            ];
+            for (field, duration) in durations {
+                if duration.is_some_and(|seconds| !seconds.is_finite()) {
+                    issue(Severity::Error, IssueKind::NonFiniteNumber(field));
+                } else if duration.is_some_and(|seconds| seconds < 0.0) {
+                    issue(Severity::Warning, IssueKind::NegativeDuration(field));
+                }
+            }
+            if let Some(score) = &rule.score {
+                score.visit(&mut |score| {
+                    if matches!(score, Score::Constant(value) if !value.is_finite()) {
+                        issue(Severity::Error, IssueKind::NonFiniteNumber("score"));
+                    }
+                });
+            }
+
+            check_action(&rule.action, area_map, &mut issue);
+        }
+
+        issues
+    }
+}
+
+impl BehaviorTree {
+    /// Same checks as `RuleSet::validate` for the leaves, plus composites that can't work.
+    /// Issues name the node by its index in `Node::visit` order.
+    pub fn validate(&self, area_map: &AreaMap) -> Vec<RuleIssue> {
+        let mut issues = Vec::new();
+
+        self.root.visit(&mut |index, node| {
+            let mut issue = |severity, kind| {
+                issues.push(RuleIssue {
+                    severity,
+                    rule: format!("node {}", index),
+                    kind,
+                })
+            };
+
+            match node {
+                Node::Condition(condition) => {
+                    condition
+                        .visit(&mut |condition| check_condition(condition, area_map, &mut issue));
+                }
+                Node::Action(action) => check_action(action, area_map, &mut issue),
+                Node::Selector(children) | Node::Sequence(children) if children.is_empty() => {
+                    issue(
+                        Severity::Warning,
+                        IssueKind::EmptyComposite(node.variant_name()),
+                    );
+                }
+                Node::Parallel {
+                    success_threshold,
+                    children,
+                } if *success_threshold > children.len() => {
+                    issue(
+                        Severity::Warning,
+                        IssueKind::UnreachableThreshold {
+                            threshold: *success_threshold,
+                            children: children.len(),
+                        },
+                    );
+                }
+                _ => {}
+            }
+        });
+
+        issues
+    }
+}
+
+fn check_condition(
+    condition: &Condition,
+    area_map: &AreaMap,
+    issue: &mut impl FnMut(Severity, IssueKind),
+) {
+    match condition {
+        Condition::InArea(area)
+        | Condition::AreaDistanceBelow { area, .. }
+        | Condition::EnemyInArea(area)
+        | Condition::AreaVisible(area) => {
+            if area_map.get_center(area.clone()).is_none() {
+                issue(Severity::Error, IssueKind::UnknownArea(area.clone()));
+            }
+        }
+        Condition::And(conditions) if conditions.is_empty() => {
+            issue(Severity::Warning, IssueKind::EmptyComposite("And"));
+        }
+        Condition::Or(conditions) if conditions.is_empty() => {
+            issue(Severity::Warning, IssueKind::EmptyComposite("Or"));
+        }
+        _ => {}
+    }
+}
+
+fn check_action(action: &Action, area_map: &AreaMap, issue: &mut impl FnMut(Severity, IssueKind)) {
+    action.visit(&mut |action| {
+        let targets = match action {
+            Action::MoveToArea(area) | Action::GuardArea { area, .. } => std::slice::from_ref(area),
+            Action::PatrolAreas(areas) => areas.as_slice(),
+            _ => &[],
+        };
+        for area in targets {
+            if area_map.get_center(area.clone()).is_none() {
+                issue(Severity::Error, IssueKind::UnknownArea(area.clone()));
+            } else if !is_connected(area, area_map) {
+                issue(Severity::Warning, IssueKind::DisconnectedArea(area.clone()));
+            }
+        }
+
+        match action {
+            Action::Sequence(steps) if steps.is_empty() => {
+                issue(Severity::Warning, IssueKind::EmptyComposite("Sequence"));
+            }
+            Action::PatrolAreas(areas) if areas.is_empty() => {
+                issue(Severity::Warning, IssueKind::EmptyComposite("PatrolAreas"));
+            }
+            _ => {}
+        }
+    });
+}
+
+/// Whether `area` is a neighbor of some other area. Trivially true on single-area arenas.
+fn is_connected(area: &AreaID, area_map: &AreaMap) -> bool {
+    area_map.areas.len() < 2
+        || area_map
+            .areas
+            .iter()
+            .any(|other| other.neighbors.contains(area))
+}
+
+/// Whether any issue is severe enough to refuse the rule set.
+pub fn has_errors(issues: &[RuleIssue]) -> bool {
+    issues.iter().any(|issue| issue.severity == Severity::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::areas::Area;
+    use serde_json::{json, Value};
+
+    /// `A` and `B` are linked, `Island` isn't linked to anything.
+    fn area_map() -> AreaMap {
+        let mut a = Area::new("A".into(), 0, 0, 4, 4);
+        let mut b = Area::new("B".into(), 5, 0, 9, 4);
+        a.neighbors.push(b.id.clone());
+        b.neighbors.push(a.id.clone());
+        AreaMap::new(vec![a, b, Area::new("Island".into(), 0, 5, 4, 9)])
+    }
+
+    fn rule(name: &str, priority: i32, condition: Value, action: Value) -> Value {
+        json!({ "name": name, "priority": priority, "condition": condition, "action": action })
+    }
+
+    fn rule_set(rules: Vec<Value>) -> RuleSet {
+        serde_json::from_value(json!({ "rules": rules })).unwrap()
+    }
+
+    /// A condition that doesn't always hold.
+    fn sometimes() -> Value {
+        json!("IsEnemyVisible")
+    }
+
+    fn issues(rule_set: &RuleSet) -> Vec<(Severity, IssueKind)> {
+        let issues = rule_set.validate(&area_map());
+        issues
+            .into_iter()
+            .map(|issue| (issue.severity, issue.kind))
+            .collect()
+    }
+
+    #[test]
+    fn unknown_areas_are_errors() {
+        let rules = rule_set(vec![
+            rule("Look", 2, json!({ "InArea": "Nowhere" }), json!("Idle")),
+            rule("Go", 1, sometimes(), json!({ "MoveToArea": "Nowhere" })),
+        ]);
+        let unknown = (Severity::Error, IssueKind::UnknownArea("Nowhere".into()));
+        assert_eq!(issues(&rules), vec![unknown.clone(), unknown]);
+    }
+
+    #[test]
+    fn disconnected_areas_are_warnings() {
+        let rules = rule_set(vec![
+            rule("Go", 2, sometimes(), json!({ "MoveToArea": "Island" })),
+            rule("Back", 1, sometimes(), json!({ "MoveToArea": "B" })),
+        ]);
+        assert_eq!(
+            issues(&rules),
+            vec![(
+                Severity::Warning,
+                IssueKind::DisconnectedArea("Island".into())
+            )]
+        );
+    }
+
+    #[test]
+    fn rules_after_an_unconditional_one_are_shadowed() {
+        let mut rules = rule_set(vec![
+            rule("Always", 2, json!("True"), json!("Idle")),
+            rule("Never", 1, sometimes(), json!("Idle")),
+        ]);
+        let shadowed = IssueKind::Shadowed {
+            by: "Always".to_string(),
+        };
+        assert_eq!(issues(&rules), vec![(Severity::Warning, shadowed)]);
+
+        rules.strategy = Strategy::Utility;
+        assert_eq!(issues(&rules), vec![]);
+    }
+
+    #[test]
+    fn shared_priorities_are_warnings() {
+        let rules = rule_set(vec![
+            rule("First", 1, sometimes(), json!("Idle")),
+            rule("Second", 1, sometimes(), json!("Idle")),
+        ]);
+        let duplicate = IssueKind::DuplicatePriority {
+            priority: 1,
+            other: "First".to_string(),
+        };
+        assert_eq!(issues(&rules), vec![(Severity::Warning, duplicate)]);
+    }
+
+    #[test]
+    fn empty_composites_are_warnings() {
+        let rules = rule_set(vec![
+            rule(
+                "Nothing",
+                2,
+                json!({ "And": [] }),
+                json!({ "Sequence": [] }),
+            ),
+            rule(
+                "Nowhere",
+                1,
+                json!({ "Or": [] }),
+                json!({ "PatrolAreas": [] }),
+            ),
+        ]);
+        let empty = |name| (Severity::Warning, IssueKind::EmptyComposite(name));
+        assert_eq!(
+            issues(&rules),
+            vec![
+                empty("And"),
+                empty("Sequence"),
+                empty("Or"),
+                empty("PatrolAreas")
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_durations_are_warnings() {
+        let mut rules = rule_set(vec![rule("Go", 1, sometimes(), json!("Idle"))]);
+        rules.rules[0].min_duration = Some(-1.0);
+        rules.rules[0].cooldown = Some(-1.0);
+        assert_eq!(
+            issues(&rules),
+            vec![
+                (
+                    Severity::Warning,
+                    IssueKind::NegativeDuration("min_duration")
+                ),
+                (Severity::Warning, IssueKind::NegativeDuration("cooldown")),
+            ]
+        );
+    }
+
+    #[test]
+    fn shared_names_are_errors() {
+        let rules = rule_set(vec![
+            rule("Go", 2, sometimes(), json!("Idle")),
+            rule("Go", 1, sometimes(), json!("Idle")),
+        ]);
+        assert_eq!(
+            issues(&rules),
+            vec![(Severity::Error, IssueKind::DuplicateName)]
+        );
+    }
+
+    #[test]
+    fn non_finite_numbers_are_errors() {
+        let mut rules = rule_set(vec![rule("Go", 1, sometimes(), json!("Idle"))]);
+        rules.rules[0].min_duration = Some(f32::NAN);
+        rules.rules[0].cooldown = Some(f32::INFINITY);
+        rules.rules[0].score = Some(Score::Constant(f32::NAN));
+        assert_eq!(
+            issues(&rules),
+            vec![
+                (Severity::Error, IssueKind::NonFiniteNumber("min_duration")),
+                (Severity::Error, IssueKind::NonFiniteNumber("cooldown")),
+                (Severity::Error, IssueKind::NonFiniteNumber("score")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parallel_thresholds_above_the_children_are_warnings() {
+        let tree: BehaviorTree = serde_json::from_value(json!({
+            "root": {
+                "Parallel": {
+                    "success_threshold": 3,
+                    "children": [{ "Action": "Idle" }, { "Selector": [] }],
+                },
+            },
+        }))
+        .unwrap();
+        let unreachable = IssueKind::UnreachableThreshold {
+            threshold: 3,
+            children: 2,
+        };
+        assert_eq!(
+            tree.validate(&area_map()),
+            vec![
+                RuleIssue {
+                    severity: Severity::Warning,
+                    rule: "node 0".to_string(),
+                    kind: unreachable,
+                },
+                RuleIssue {
+                    severity: Severity::Warning,
+                    rule: "node 2".to_string(),
+                    kind: IssueKind::EmptyComposite("Selector"),
+                },
+            ]
+        );
+    }
+}
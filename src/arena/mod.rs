@@ -1,6 +1,8 @@
 use crate::building::{Structure, StructureType};
 use crate::pathfinding::NavGraph;
-use areas::{Area, AreaMap};
+use crate::simulation::SimulationSet;
+use crate::PLAYER_SIZE;
+use areas::{Area, AreaID, AreaMap};
 use bevy::platform::collections::HashMap;
 use bevy::prelude::*;
 use rand::prelude::*;
@@ -16,6 +18,60 @@ pub struct ArenaDescription {
     pub resource_respawn_time: f32,
 }
 
+// . = Floor
+// X = Wall
+// O = Obstacle
+// T = Turret Resource
+// B = Block (Obstacle) Resource
+pub const DEFAULT_LAYOUT: &str = "
+XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+X........X.............................X
+X...T....X.............................X
+X........X.............................X
+X...XXXXXX.......XXXXXXXXXXXXXX....XXXXX
+X...XXXXXX.......XXXXXXXXXXXXXX....XXXXX
+X......................................X
+X......................................X
+X......................................X
+X......................................X
+X......................................X
+X......................................X
+X...XXXXXXXXXXXX...XXXX...XXXXXXXXXXXX.X
+X...XXXXXXXXXXXX...XXXX...XXXXXXXXXXXX.X
+X..................XXXX................X
+X..................XXXX................X
+X......................................X
+X......................................X
+X......................................X
+X......................................X
+X...XXXXXX.......XXXXXXXXXXXXXX....XXXXX
+X...XXXXXX.......XXXXXXXXXXXXXX....XXXXX
+X......................................X
+X.............................X........X
+X.............................X....T...X
+X.............................X........X
+XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+";
+
+impl Default for ArenaDescription {
+    /// The arena the game and the headless tools are played on.
+    fn default() -> Self {
+        Self {
+            layout: DEFAULT_LAYOUT.to_string(),
+            areas: vec![
+                Area::new(AreaID("UserBase".to_string()), 0, 0, 10, 10),
+                Area::new(AreaID("EnemyBase".to_string()), 30, 0, 39, 10),
+                Area::new(AreaID("CenterArena".to_string()), 11, 0, 29, 27),
+                Area::new(AreaID("NorthCorridor".to_string()), 0, 11, 39, 27),
+            ],
+            player_spawn: Vec3::new(18.0, PLAYER_SIZE.y / 2.0, 10.0),
+            ai_spawn: Vec3::new(26.0, PLAYER_SIZE.y / 2.0, 10.0),
+            enemy_spawn: Vec3::new(142.0, PLAYER_SIZE.y / 2.0, 10.0),
+            resource_respawn_time: 30.0,
+        }
+    }
+}
+
 pub struct ArenaPlugin {
     description: ArenaDescription,
 }
@@ -75,7 +131,10 @@ impl Plugin for ArenaPlugin {
             PostStartup,
             (generate_nav_nodes, calculate_area_connectivity),
         )
-        .add_systems(Update, resource_respawn_system);
+        .add_systems(
+            FixedUpdate,
+            resource_respawn_system.in_set(SimulationSet::World),
+        );
     }
 }
 
@@ -84,8 +143,6 @@ fn resource_respawn_system(
     time: Res<Time>,
     mut spawner_query: Query<(&mut ResourceSpawner, &Transform)>,
     grid: Res<ArenaGrid>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     config: Res<ArenaConfig>,
 ) {
     for (mut spawner, transform) in spawner_query.iter_mut() {
@@ -103,17 +160,8 @@ fn resource_respawn_system(
                 // Let's check if there is already a collectible there?
                 // For simplicity, just spawn it. The collection logic handles despawning.
 
-                let collectible_mesh = meshes.add(Cuboid::new(0.5, 0.5, 0.5));
-                let collectible_mat = if matches!(spawner.ty, CollectibleType::Turret) {
-                    materials.add(Color::srgb(0.0, 0.0, 1.0))
-                } else {
-                    materials.add(Color::srgb(1.0, 1.0, 0.0))
-                };
-
                 commands.spawn((
                     Collectible { ty: spawner.ty },
-                    Mesh3d(collectible_mesh),
-                    MeshMaterial3d(collectible_mat),
                     Transform::from_translation(transform.translation + Vec3::Y * 0.5),
                 ));
             }
@@ -182,6 +230,8 @@ pub fn calculate_area_connectivity(
     info!("Area connectivity calculated.");
 }
 
+pub const WALL_HEIGHT: f32 = 8.0;
+
 #[derive(Resource)]
 pub struct ArenaConfig {
     pub width: u32,
@@ -189,6 +239,25 @@ pub struct ArenaConfig {
     pub tile_size: f32,
 }
 
+impl ArenaConfig {
+    /// Grid coordinates of the tile containing `position`.
+    pub fn world_to_tile(&self, position: Vec3) -> (u32, u32) {
+        (
+            ((position.x - self.tile_size * 0.5) / self.tile_size).floor() as u32,
+            ((position.z - self.tile_size * 0.5) / self.tile_size).floor() as u32,
+        )
+    }
+
+    /// Center of a tile, on the ground.
+    pub fn tile_to_world(&self, x: u32, y: u32) -> Vec3 {
+        Vec3::new(
+            x as f32 * self.tile_size + self.tile_size * 0.5,
+            0.0,
+            y as f32 * self.tile_size + self.tile_size * 0.5,
+        )
+    }
+}
+
 #[derive(Resource, Default)]
 pub struct ArenaGrid {
     pub tiles: HashMap<(u32, u32), Entity>,
@@ -217,27 +286,13 @@ pub struct Collectible {
     pub ty: CollectibleType,
 }
 
+/// Spawns the gameplay entities of the arena. Meshes are attached by `VisualsPlugin`.
 fn spawn_arena(
     mut commands: Commands,
     config: Res<ArenaConfig>,
     layout: Res<ArenaMapLayout>,
     mut grid: ResMut<ArenaGrid>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    let wall_height = 8.0;
-    let floor_mesh = meshes.add(Cuboid::new(config.tile_size, 0.1, config.tile_size));
-    let wall_mesh = meshes.add(Cuboid::new(config.tile_size, wall_height, config.tile_size));
-    let obstacle_mesh = meshes.add(Cuboid::new(
-        config.tile_size * 0.8,
-        wall_height * 0.8,
-        config.tile_size * 0.8,
-    ));
-
-    let floor_mat = materials.add(Color::srgb(0.3, 0.5, 0.3));
-    let wall_mat = materials.add(Color::srgb(0.2, 0.2, 0.2));
-    let obstacle_mat = materials.add(Color::srgb(0.6, 0.3, 0.3));
-
     let lines: Vec<&str> = layout.0.trim().lines().collect();
 
     for (y, line) in lines.iter().enumerate() {
@@ -252,12 +307,7 @@ fn spawn_arena(
             );
 
             let tile_entity = commands
-                .spawn((
-                    Tile { x, y },
-                    Mesh3d(floor_mesh.clone()),
-                    MeshMaterial3d(floor_mat.clone()),
-                    Transform::from_translation(position),
-                ))
+                .spawn((Tile { x, y }, Transform::from_translation(position)))
                 .id();
 
             grid.tiles.insert((x, y), tile_entity);
@@ -272,9 +322,7 @@ fn spawn_arena(
                                 ty: StructureType::Wall,
                                 collider_scale: 1.0,
                             },
-                            Mesh3d(wall_mesh.clone()),
-                            MeshMaterial3d(wall_mat.clone()),
-                            Transform::from_translation(position + Vec3::Y * (wall_height / 2.0)),
+                            Transform::from_translation(position + Vec3::Y * (WALL_HEIGHT / 2.0)),
                         ))
                         .id();
                     grid.occupants.insert((x, y), wall_entity);
@@ -288,9 +336,7 @@ fn spawn_arena(
                                 ty: StructureType::Obstacle,
                                 collider_scale: 1.0,
                             },
-                            Mesh3d(obstacle_mesh.clone()),
-                            MeshMaterial3d(obstacle_mat.clone()),
-                            Transform::from_translation(position + Vec3::Y * (wall_height * 0.4)),
+                            Transform::from_translation(position + Vec3::Y * (WALL_HEIGHT * 0.4)),
                         ))
                         .id();
                     grid.occupants.insert((x, y), obstacle_entity);
@@ -0,0 +1,335 @@
+//! Local stand-in for the remote coaching service.
+//!
+//! Accepts WebSocket connections from the game, decodes `ClientMessage`s and answers with
+//! `ServerMessage::UpdateRuleSet` whenever the selected strategy decides to change the rules.
+//! Clients must open with a `Hello` of the same protocol version; rule sets using variants the
+//! client did not advertise are never sent. Match events are merged into one log per session,
+//! so a client reconnecting with the same session resumes where it left off.
+//!
+//! Usage: `coach_server [ADDR] [STRATEGY]`
+//!
+//! Strategies:
+//! - `default`: send `RuleSet::default()` once
+//! - `turret-only`: send `RuleSet::new_turret_only()` once
+//! - `turret-after-deaths=N`: switch to `RuleSet::new_turret_only()` after N eliminations
+
+use bevy_test::{
+    Capabilities, ClientMessage, GameEvent, MatchLog, ProtocolError, RuleSet, RuleSetTarget,
+    ServerMessage, GAME_BUILD, PROTOCOL_VERSION,
+};
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tungstenite::{accept, Message, WebSocket};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:9001";
+const DEFAULT_STRATEGY: &str = "turret-after-deaths=1";
+
+/// Decides which rule set the AI should run, based on what the game reports.
+trait CoachStrategy: Send {
+    fn name(&self) -> String;
+
+    /// Returns a new rule set and the players it is meant for, or `None` to keep the current one.
+    /// `match_log` holds every event of the session received so far.
+    fn on_message(
+        &mut self,
+        message: &ClientMessage,
+        match_log: &MatchLog,
+    ) -> Option<(RuleSetTarget, RuleSet)>;
+}
+
+/// Sends a fixed rule set once, in reply to the first message.
+struct FixedRuleSet {
+    name: &'static str,
+    rule_set: RuleSet,
+    sent: bool,
+}
+
+impl CoachStrategy for FixedRuleSet {
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn on_message(
+        &mut self,
+        _message: &ClientMessage,
+        _match_log: &MatchLog,
+    ) -> Option<(RuleSetTarget, RuleSet)> {
+        if self.sent {
+            return None;
+        }
+        self.sent = true;
+        Some((RuleSetTarget::All, self.rule_set.clone()))
+    }
+}
+
+/// Switches to `RuleSet::new_turret_only` once enough players were eliminated.
+struct TurretOnlyAfterDeaths {
+    deaths: usize,
+    switched: bool,
+}
+
+impl CoachStrategy for TurretOnlyAfterDeaths {
+    fn name(&self) -> String {
+        format!("turret-after-deaths={}", self.deaths)
+    }
+
+    fn on_message(
+        &mut self,
+        _message: &ClientMessage,
+        match_log: &MatchLog,
+    ) -> Option<(RuleSetTarget, RuleSet)> {
+        let deaths = match_log
+            .events
+            .iter()
+            .filter(|event| matches!(event, GameEvent::PlayerEliminated { .. }))
+            .count();
+
+        if self.switched || deaths < self.deaths {
+            return None;
+        }
+        self.switched = true;
+        Some((RuleSetTarget::All, RuleSet::new_turret_only()))
+    }
+}
+
+/// State kept per game session, surviving reconnects.
+struct Session {
+    match_log: MatchLog,
+    strategy: Box<dyn CoachStrategy>,
+    /// Id of the next `UpdateRuleSet`, so acks from an earlier connection stay unambiguous.
+    next_update_id: u64,
+}
+
+type Sessions = Arc<Mutex<HashMap<u64, Session>>>;
+
+fn parse_strategy(spec: &str) -> Option<Box<dyn CoachStrategy>> {
+    match spec.split_once('=') {
+        Some(("turret-after-deaths", deaths)) => {
+            let deaths = deaths.parse().ok()?;
+            Some(Box::new(TurretOnlyAfterDeaths {
+                deaths,
+                switched: false,
+            }))
+        }
+        None if spec == "default" => Some(Box::new(FixedRuleSet {
+            name: "default",
+            rule_set: RuleSet::default(),
+            sent: false,
+        })),
+        None if spec == "turret-only" => Some(Box::new(FixedRuleSet {
+            name: "turret-only",
+            rule_set: RuleSet::new_turret_only(),
+            sent: false,
+        })),
+        _ => None,
+    }
+}
+
+fn send(socket: &mut WebSocket<TcpStream>, message: &ServerMessage) -> bool {
+    let json = serde_json::to_string(message).expect("ServerMessage is always serializable");
+    socket.send(Message::text(json)).is_ok()
+}
+
+fn handle_connection(stream: TcpStream, sessions: Sessions, strategy_spec: String) {
+    let peer = stream
+        .peer_addr()
+        .map(|addr| addr.to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+
+    let mut socket = match accept(stream) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("[{}] WebSocket handshake failed: {}", peer, e);
+            return;
+        }
+    };
+    println!("[{}] Client connected", peer);
+
+    // Set by a successful `Hello`.
+    let mut handshake: Option<(u64, Capabilities)> = None;
+
+    loop {
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(e) => {
+                eprintln!("[{}] Connection error: {}", peer, e);
+                break;
+            }
+        };
+
+        let message = match ClientMessage::decode(text.as_str()) {
+            Ok(message) => message,
+            Err(error) => {
+                eprintln!("[{}] Rejected client message: {}", peer, error);
+                if !send(&mut socket, &ServerMessage::Rejected(error)) {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        if let ClientMessage::Hello {
+            protocol_version,
+            game_build,
+            capabilities,
+            session,
+        } = &message
+        {
+            if *protocol_version != PROTOCOL_VERSION {
+                let error = ProtocolError::VersionMismatch {
+                    expected: PROTOCOL_VERSION,
+                    received: *protocol_version,
+                };
+                eprintln!("[{}] {}", peer, error);
+                send(&mut socket, &ServerMessage::Rejected(error));
+                break;
+            }
+
+            // The sessions lock is released before any socket I/O, so a slow client doesn't
+            // hold up the others.
+            let next_event_seq = {
+                let mut sessions = sessions.lock().unwrap();
+                let entry = sessions.entry(*session).or_insert_with(|| Session {
+                    match_log: MatchLog::default(),
+                    strategy: parse_strategy(&strategy_spec).expect("strategy checked at startup"),
+                    next_update_id: 0,
+                });
+                println!(
+                    "[{}] Handshake from game build {}, session {:x}, strategy {}, {} events known",
+                    peer,
+                    game_build,
+                    session,
+                    entry.strategy.name(),
+                    entry.match_log.next_seq()
+                );
+                entry.match_log.next_seq()
+            };
+
+            handshake = Some((*session, capabilities.clone()));
+            let welcome = ServerMessage::Welcome {
+                protocol_version: PROTOCOL_VERSION,
+                server_build: GAME_BUILD.to_string(),
+                next_event_seq,
+            };
+            if !send(&mut socket, &welcome) {
+                break;
+            }
+            continue;
+        }
+
+        if let ClientMessage::ProtocolError(error) = &message {
+            eprintln!("[{}] Client rejected our message: {}", peer, error);
+            continue;
+        }
+
+        let Some((session_id, capabilities)) = &handshake else {
+            let rejected = ServerMessage::Rejected(ProtocolError::HandshakeRequired);
+            if !send(&mut socket, &rejected) {
+                break;
+            }
+            continue;
+        };
+
+        let mut replies = Vec::new();
+        {
+            let mut sessions = sessions.lock().unwrap();
+            let session = sessions
+                .get_mut(session_id)
+                .expect("session is created by the handshake");
+
+            match &message {
+                ClientMessage::PushMatchEvents { from_seq, events } => {
+                    if !session.match_log.append_from(*from_seq, events) {
+                        eprintln!(
+                            "[{}] Gap in match events: got {}, expected {}",
+                            peer,
+                            from_seq,
+                            session.match_log.next_seq()
+                        );
+                    }
+                    replies.push(ServerMessage::MatchEventsAck {
+                        next_seq: session.match_log.next_seq(),
+                    });
+                }
+                ClientMessage::RuleSetApplied {
+                    update_id,
+                    applied_to,
+                    time,
+                } => {
+                    println!(
+                        "[{}] Rule set update {} applied to {:?} at {:.1}s",
+                        peer, update_id, applied_to, time
+                    );
+                }
+                _ => {}
+            }
+
+            if let Some((target, rule_set)) =
+                session.strategy.on_message(&message, &session.match_log)
+            {
+                match capabilities.check_rule_set(&rule_set) {
+                    Ok(()) => {
+                        println!(
+                            "[{}] Sending rule set update {} with {} rules to {:?}",
+                            peer,
+                            session.next_update_id,
+                            rule_set.rules.len(),
+                            target
+                        );
+                        replies.push(ServerMessage::UpdateRuleSet {
+                            update_id: session.next_update_id,
+                            target,
+                            rule_set,
+                        });
+                        session.next_update_id += 1;
+                    }
+                    Err(error) => eprintln!("[{}] Not sending rule set: {}", peer, error),
+                }
+            }
+        }
+
+        if !replies.iter().all(|reply| send(&mut socket, reply)) {
+            eprintln!("[{}] Failed to send reply", peer);
+            break;
+        }
+    }
+
+    println!("[{}] Client disconnected", peer);
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    let strategy_spec = args.next().unwrap_or_else(|| DEFAULT_STRATEGY.to_string());
+
+    if parse_strategy(&strategy_spec).is_none() {
+        eprintln!("Unknown strategy: {}", strategy_spec);
+        std::process::exit(2);
+    }
+
+    let listener = TcpListener::bind(&addr).unwrap_or_else(|e| {
+        eprintln!("Could not bind {}: {}", addr, e);
+        std::process::exit(1);
+    });
+    println!(
+        "Coach server listening on ws://{}, strategy: {}",
+        addr, strategy_spec
+    );
+
+    let sessions = Sessions::default();
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let sessions = sessions.clone();
+                let strategy_spec = strategy_spec.clone();
+                thread::spawn(move || handle_connection(stream, sessions, strategy_spec));
+            }
+            Err(e) => eprintln!("Failed to accept connection: {}", e),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Evolves rule sets against a fixed pool of opponents.
+//!
+//! Every generation, each candidate plays headless matches against every opponent, once per
+//! match seed from each side. Its fitness is the average score (1 win, 0.5 draw,
+//! 0 loss). The next generation keeps the best candidates and fills up with mutated
+//! crossovers of tournament-selected parents.
+//!
+//! Writes the best rule sets as `best_N.json` and the per-generation fitness as
+//! `fitness_history.csv` to the output directory. Without opponent files, the pool is
+//! `RuleSet::default()` and `RuleSet::new_turret_only()`.
+//!
+//! Usage: `evolve [--generations N] [--population N] [--seeds N] [--seed N] [--out DIR]
+//! [OPPONENT.json...]`
+
+use bevy_test::ai::dsl;
+use bevy_test::ai::evolution::{crossover, mutate};
+use bevy_test::arena::ArenaDescription;
+use bevy_test::headless::{HeadlessMatch, DEFAULT_MAX_DURATION};
+use bevy_test::RuleSet;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+const DEFAULT_GENERATIONS: usize = 30;
+const DEFAULT_POPULATION: usize = 24;
+const DEFAULT_SEEDS: u64 = 4;
+/// Candidates copied unchanged into the next generation.
+const ELITE: usize = 2;
+const TOURNAMENT_SIZE: usize = 3;
+/// Number of `best_N.json` files written.
+const KEEP_BEST: usize = 5;
+
+struct Options {
+    generations: usize,
+    population: usize,
+    seeds: u64,
+    seed: u64,
+    out: PathBuf,
+    opponents: Vec<String>,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: evolve [--generations N] [--population N] [--seeds N] [--seed N] [--out DIR] \
+         [OPPONENT.json...]"
+    );
+    std::process::exit(2);
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        generations: DEFAULT_GENERATIONS,
+        population: DEFAULT_POPULATION,
+        seeds: DEFAULT_SEEDS,
+        seed: 0,
+        out: PathBuf::from("evolved"),
+        opponents: Vec::new(),
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--generations" => options.generations = value().parse().unwrap_or_else(|_| usage()),
+            "--population" => options.population = value().parse().unwrap_or_else(|_| usage()),
+            "--seeds" => options.seeds = value().parse().unwrap_or_else(|_| usage()),
+            "--seed" => options.seed = value().parse().unwrap_or_else(|_| usage()),
+            "--out" => options.out = PathBuf::from(value()),
+            _ => options.opponents.push(arg),
+        }
+    }
+
+    if options.population <= ELITE {
+        eprintln!("Population must be larger than {}", ELITE);
+        std::process::exit(2);
+    }
+    options
+}
+
+/// Average score of `candidate` against every opponent and match seed, from both sides.
+fn fitness(candidate: &RuleSet, opponents: &[RuleSet], seeds: u64) -> f64 {
+    let mut total = 0.0;
+    for opponent in opponents {
+        for seed in 0..seeds {
+            // Both sides on the same seed, so the spawn doesn't decide the result.
+            for side in 0..2 {
+                let rule_sets = if side == 0 {
+                    [candidate.clone(), opponent.clone()]
+                } else {
+                    [opponent.clone(), candidate.clone()]
+                };
+                let result = HeadlessMatch::new(seed, rule_sets).run(DEFAULT_MAX_DURATION);
+                total += result.outcome.score(side);
+            }
+        }
+    }
+    total / (opponents.len() as u64 * seeds * 2) as f64
+}
+
+/// Evaluates all candidates in parallel, keeping their order.
+fn evaluate(population: &[RuleSet], opponents: &[RuleSet], seeds: u64) -> Vec<f64> {
+    let scores = Mutex::new(vec![0.0; population.len()]);
+    let next = AtomicUsize::new(0);
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(candidate) = population.get(index) else {
+                    break;
+                };
+                let score = fitness(candidate, opponents, seeds);
+                scores.lock().unwrap()[index] = score;
+            });
+        }
+    });
+    scores.into_inner().unwrap()
+}
+
+fn select<'a>(ranked: &'a [(f64, RuleSet)], rng: &mut impl Rng) -> &'a RuleSet {
+    // `ranked` is sorted best first, so the lowest index drawn wins.
+    let best = (0..TOURNAMENT_SIZE)
+        .map(|_| rng.random_range(0..ranked.len()))
+        .min()
+        .unwrap();
+    &ranked[best].1
+}
+
+fn main() {
+    let options = parse_options();
+
+    let opponents: Vec<RuleSet> = if options.opponents.is_empty() {
+        vec![RuleSet::default(), RuleSet::new_turret_only()]
+    } else {
+        options
+            .opponents
+            .iter()
+            .map(dsl::load)
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                eprintln!("Could not load opponent {}", e);
+                std::process::exit(1);
+            })
+    };
+
+    let areas: Vec<_> = ArenaDescription::default()
+        .areas
+        .into_iter()
+        .map(|area| area.id)
+        .collect();
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    // Start from the hand-written rule sets and their mutants.
+    let mut population = vec![RuleSet::default(), RuleSet::new_turret_only()];
+    while population.len() < options.population {
+        let mut candidate = population[population.len() % 2].clone();
+        for _ in 0..rng.random_range(1..=3) {
+            mutate(&mut candidate, &areas, &mut rng);
+        }
+        population.push(candidate);
+    }
+
+    let mut history = String::from("generation,best,mean,worst\n");
+    let mut ranked = Vec::new();
+
+    for generation in 0..options.generations {
+        let scores = evaluate(&population, &opponents, options.seeds);
+        ranked = scores.into_iter().zip(population).collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let best = ranked[0].0;
+        let worst = ranked[ranked.len() - 1].0;
+        let mean = ranked.iter().map(|(score, _)| score).sum::<f64>() / ranked.len() as f64;
+        println!(
+            "Generation {:>3}: best {:.3}, mean {:.3}, worst {:.3} ({} rules in best)",
+            generation,
+            best,
+            mean,
+            worst,
+            ranked[0].1.rules.len()
+        );
+        let _ = writeln!(history, "{},{},{},{}", generation, best, mean, worst);
+
+        population = ranked
+            .iter()
+            .take(ELITE)
+            .map(|(_, rule_set)| rule_set.clone())
+            .collect();
+        while population.len() < options.population {
+            let mut child = crossover(
+                select(&ranked, &mut rng),
+                select(&ranked, &mut rng),
+                &mut rng,
+            );
+            mutate(&mut child, &areas, &mut rng);
+            population.push(child);
+        }
+    }
+
+    if let Err(e) = std::fs::create_dir_all(&options.out) {
+        eprintln!("Could not create {}: {}", options.out.display(), e);
+        std::process::exit(1);
+    }
+
+    let write = |name: String, contents: String| {
+        let path = options.out.join(name);
+        if let Err(e) = std::fs::write(&path, contents) {
+            eprintln!("Could not write {}: {}", path.display(), e);
+        }
+    };
+
+    for (rank, (score, rule_set)) in ranked.iter().take(KEEP_BEST).enumerate() {
+        let json = serde_json::to_string_pretty(rule_set).expect("RuleSet is always serializable");
+        write(format!("best_{}.json", rank + 1), json);
+        println!("#{} fitness {:.3}", rank + 1, score);
+    }
+    write("fitness_history.csv".to_string(), history);
+
+    println!("Results written to {}", options.out.display());
+}
@@ -0,0 +1,56 @@
+//! Checks rule set files with `RuleSet::validate` against the default arena.
+//!
+//! Files ending in `.rules` are read in the text syntax of `ai::dsl`, all others as JSON.
+//! Prints one line per issue and exits with status 1 if any file has errors. With
+//! `--print`, also prints every loaded rule set in the text syntax, which converts JSON
+//! rule files to `.rules` files.
+//!
+//! Usage: `lint_rules [--print] RULES...`
+
+use bevy_test::ai::dsl;
+use bevy_test::ai::validation::has_errors;
+use bevy_test::arena::ArenaDescription;
+use bevy_test::headless::arena_area_map;
+
+fn main() {
+    let mut print = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--print" => print = true,
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("Usage: lint_rules [--print] RULES...");
+        std::process::exit(2);
+    }
+
+    let area_map = arena_area_map(ArenaDescription::default());
+    let mut failed = false;
+
+    for path in &paths {
+        let rule_set = match dsl::load(path) {
+            Ok(rule_set) => rule_set,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let issues = rule_set.validate(&area_map);
+        for issue in &issues {
+            eprintln!("{}: {}", path, issue);
+        }
+        failed |= has_errors(&issues);
+
+        if print {
+            print!("{}", dsl::print(&rule_set));
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,214 @@
+//! Round-robin tournament between rule sets.
+//!
+//! Loads every `RuleSet` file given on the command line, JSON or `.rules` text, and plays
+//! each pairing in headless matches, from both sides on every seed, so neither rule set
+//! profits from the spawn. Prints the win/loss/draw matrix and Elo ratings.
+//!
+//! Usage: `tournament [--seeds N] [--max-time SECONDS] RULES.json RULES.json...`
+
+use bevy_test::ai::dsl;
+use bevy_test::headless::{HeadlessMatch, DEFAULT_MAX_DURATION};
+use bevy_test::RuleSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+const DEFAULT_SEEDS: u64 = 20;
+const INITIAL_ELO: f64 = 1500.0;
+const ELO_K: f64 = 16.0;
+
+struct Entrant {
+    name: String,
+    rule_set: RuleSet,
+}
+
+/// One match of the tournament, `a` and `b` being entrant indices.
+struct Game {
+    a: usize,
+    b: usize,
+    seed: u64,
+    /// Side `a` plays on, `b` takes the other one.
+    side: usize,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Record {
+    wins: u32,
+    losses: u32,
+    draws: u32,
+}
+
+fn load_entrant(path: &str) -> Result<Entrant, String> {
+    let rule_set = dsl::load(path)?;
+    let name = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string());
+    Ok(Entrant { name, rule_set })
+}
+
+/// Plays a game and returns the score of entrant `a`: 1.0 win, 0.5 draw, 0.0 loss.
+fn play(game: &Game, entrants: &[Entrant], max_duration: f32) -> f64 {
+    let (a, b) = (&entrants[game.a].rule_set, &entrants[game.b].rule_set);
+    let rule_sets = if game.side == 0 {
+        [a.clone(), b.clone()]
+    } else {
+        [b.clone(), a.clone()]
+    };
+    let result = HeadlessMatch::new(game.seed, rule_sets).run(max_duration);
+    result.outcome.score(game.side)
+}
+
+fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+fn main() {
+    let mut seeds = DEFAULT_SEEDS;
+    let mut max_duration = DEFAULT_MAX_DURATION;
+    let mut paths = Vec::new();
+
+    let usage = || -> ! {
+        eprintln!("Usage: tournament [--seeds N] [--max-time SECONDS] RULES.json...");
+        std::process::exit(2);
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seeds" => {
+                seeds = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--max-time" => {
+                max_duration = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.len() < 2 {
+        eprintln!("Need at least two rule set files");
+        std::process::exit(2);
+    }
+
+    let entrants: Vec<Entrant> = paths
+        .iter()
+        .map(|path| load_entrant(path))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("Could not load rule set {}", e);
+            std::process::exit(1);
+        });
+
+    let mut games = Vec::new();
+    for a in 0..entrants.len() {
+        for b in a + 1..entrants.len() {
+            for seed in 0..seeds {
+                games.extend((0..2).map(|side| Game { a, b, seed, side }));
+            }
+        }
+    }
+
+    println!(
+        "Playing {} games ({} seeds per pairing, both sides each, max {:.0}s each)",
+        games.len(),
+        seeds,
+        max_duration
+    );
+
+    // Matches are independent, so spread them over all cores.
+    let scores = Mutex::new(vec![0.0; games.len()]);
+    let next_game = AtomicUsize::new(0);
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next_game.fetch_add(1, Ordering::Relaxed);
+                let Some(game) = games.get(index) else {
+                    break;
+                };
+                let score = play(game, &entrants, max_duration);
+                scores.lock().unwrap()[index] = score;
+            });
+        }
+    });
+    let scores = scores.into_inner().unwrap();
+
+    // Records and ratings are updated in game order, independent of thread scheduling.
+    let mut records = vec![vec![Record::default(); entrants.len()]; entrants.len()];
+    let mut elo = vec![INITIAL_ELO; entrants.len()];
+    for (game, score) in games.iter().zip(&scores) {
+        let (a, b) = (game.a, game.b);
+        match *score {
+            s if s > 0.5 => {
+                records[a][b].wins += 1;
+                records[b][a].losses += 1;
+            }
+            s if s < 0.5 => {
+                records[a][b].losses += 1;
+                records[b][a].wins += 1;
+            }
+            _ => {
+                records[a][b].draws += 1;
+                records[b][a].draws += 1;
+            }
+        }
+
+        let expected = expected_score(elo[a], elo[b]);
+        elo[a] += ELO_K * (score - expected);
+        elo[b] -= ELO_K * (score - expected);
+    }
+
+    let width = entrants
+        .iter()
+        .map(|e| e.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(8);
+
+    println!("\nWin/loss/draw of row against column:");
+    print!("{:width$}", "", width = width);
+    for entrant in &entrants {
+        print!("  {:>width$}", entrant.name, width = width);
+    }
+    println!();
+    for (a, entrant) in entrants.iter().enumerate() {
+        print!("{:width$}", entrant.name, width = width);
+        for (b, record) in records[a].iter().enumerate() {
+            let cell = if a == b {
+                "-".to_string()
+            } else {
+                format!("{}/{}/{}", record.wins, record.losses, record.draws)
+            };
+            print!("  {:>width$}", cell, width = width);
+        }
+        println!();
+    }
+
+    let mut ranking: Vec<usize> = (0..entrants.len()).collect();
+    ranking.sort_by(|a, b| elo[*b].total_cmp(&elo[*a]));
+
+    println!("\nElo ratings:");
+    for (rank, index) in ranking.iter().enumerate() {
+        let (wins, losses, draws) = records[*index].iter().fold((0, 0, 0), |(w, l, d), r| {
+            (w + r.wins, l + r.losses, d + r.draws)
+        });
+        println!(
+            "{:>3}. {:width$}  {:>6.0}  ({}/{}/{})",
+            rank + 1,
+            entrants[*index].name,
+            elo[*index],
+            wins,
+            losses,
+            draws,
+            width = width
+        );
+    }
+}
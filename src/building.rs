@@ -27,7 +27,7 @@ impl Plugin for BuildingPlugin {
 }
 
 // Constants
-const BUILD_MAX_DISTANCE: f32 = 15.0;
+pub const BUILD_MAX_DISTANCE: f32 = 15.0;
 
 #[derive(Component)]
 pub struct BuildGhost;
@@ -160,11 +160,10 @@ fn handle_build_input(
     mouse_btn: Res<ButtonInput<MouseButton>>,
     ghost_query: Query<(&Transform, &Visibility), With<BuildGhost>>,
     config: Res<ArenaConfig>,
-    time: Res<Time>,
+    // Turret cooldowns are checked against the gameplay clock.
+    time: Res<Time<Fixed>>,
     mut grid: ResMut<ArenaGrid>,
     mut nav_graph: ResMut<NavGraph>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut ai_query: Query<&mut TargetDestination, With<AiPlayer>>,
     selected_query: Query<&SelectedBuildType, With<User>>,
     player_query: Query<(&PlayerID, &Transform), With<User>>,
@@ -224,13 +223,6 @@ fn handle_build_input(
                         return;
                     }
 
-                    let obstacle_mesh = meshes.add(Cuboid::new(
-                        config.tile_size * 0.8,
-                        8.0 * 0.8,
-                        config.tile_size * 0.8,
-                    ));
-                    let obstacle_mat = materials.add(Color::srgb(0.6, 0.3, 0.3));
-
                     let obstacle_entity = commands
                         .spawn((
                             Obstacle,
@@ -238,8 +230,6 @@ fn handle_build_input(
                                 ty: StructureType::Obstacle,
                                 collider_scale: 1.0,
                             },
-                            Mesh3d(obstacle_mesh),
-                            MeshMaterial3d(obstacle_mat),
                             Transform::from_translation(pos + Vec3::Y * (8.0 * 0.4)),
                         ))
                         .id();
@@ -278,15 +268,7 @@ fn handle_build_input(
                         }
                     };
 
-                    let turret_mesh = meshes.add(Cylinder::new(1.5, 3.0));
-                    let turret_mat = materials.add(Color::srgb(0.0, 0.5, 1.0));
-                    let barrel_mesh = meshes.add(Cuboid::new(0.5, 0.5, 2.0));
-                    let barrel_mat = materials.add(Color::srgb(0.2, 0.2, 0.8));
-
                     let rotation = actual_direction.to_quat();
-                    // Barrel offset should be in local space (always forward relative to turret)
-                    // Since North is -Z, forward is -Z.
-                    let barrel_offset = -Vec3::Z * 2.5;
 
                     let turret_entity = commands
                         .spawn((
@@ -300,18 +282,9 @@ fn handle_build_input(
                                 direction: actual_direction,
                                 last_shot: time.elapsed_secs() - 4.0,
                             },
-                            Mesh3d(turret_mesh),
-                            MeshMaterial3d(turret_mat),
                             Transform::from_translation(pos + Vec3::Y * 1.5)
                                 .with_rotation(rotation),
                         ))
-                        .with_children(|parent| {
-                            parent.spawn((
-                                Mesh3d(barrel_mesh),
-                                MeshMaterial3d(barrel_mat),
-                                Transform::from_translation(barrel_offset + Vec3::Y * 0.5),
-                            ));
-                        })
                         .id();
 
                     grid.occupants.insert((tile_x, tile_y), turret_entity);
@@ -1,7 +1,9 @@
 use bevy::prelude::*;
+use std::collections::VecDeque;
 
 use crate::logging::{GameEvent, MatchLog};
 use crate::player_id::PlayerID;
+use crate::simulation::SimulationSet;
 use crate::user::User;
 use crate::GameState;
 
@@ -9,14 +11,19 @@ pub struct CombatPlugin;
 
 impl Plugin for CombatPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            turret_shooting_system.run_if(in_state(GameState::Playing)),
-        );
+        app.add_message::<TurretFired>()
+            .init_resource::<DamageTracking>()
+            .add_systems(
+                FixedUpdate,
+                turret_shooting_system
+                    .run_if(in_state(GameState::Playing))
+                    .in_set(SimulationSet::Combat),
+            );
     }
 }
 
 #[derive(Component)]
+#[require(DamageHistory)]
 pub struct Hp {
     pub current: u32,
     pub max: u32,
@@ -36,6 +43,62 @@ impl Hp {
     }
 }
 
+/// Seconds of incoming damage kept in `DamageHistory`.
+pub const DAMAGE_HISTORY_SECONDS: f32 = 30.0;
+
+#[derive(Resource)]
+pub struct DamageTracking {
+    /// `Condition::IsUnderAttack` holds for this many seconds after a hit.
+    pub under_attack_window: f32,
+}
+
+impl Default for DamageTracking {
+    fn default() -> Self {
+        Self {
+            under_attack_window: 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DamageRecord {
+    pub attacker: PlayerID,
+    pub amount: u32,
+    pub time: f32,
+}
+
+/// Damage a player took recently, oldest first. Every damage source must call `record`.
+#[derive(Component, Default, Debug)]
+pub struct DamageHistory {
+    pub hits: VecDeque<DamageRecord>,
+}
+
+impl DamageHistory {
+    pub fn record(&mut self, attacker: PlayerID, amount: u32, time: f32) {
+        while self
+            .hits
+            .front()
+            .is_some_and(|hit| hit.time < time - DAMAGE_HISTORY_SECONDS)
+        {
+            self.hits.pop_front();
+        }
+        self.hits.push_back(DamageRecord {
+            attacker,
+            amount,
+            time,
+        });
+    }
+
+    pub fn last_hit(&self) -> Option<&DamageRecord> {
+        self.hits.back()
+    }
+
+    /// Whether any damage was taken in the last `window` seconds before `now`.
+    pub fn hit_within(&self, now: f32, window: f32) -> bool {
+        self.last_hit().is_some_and(|hit| now - hit.time <= window)
+    }
+}
+
 pub use bevy_test::TurretDirection;
 
 impl TurretDirection {
@@ -101,38 +164,38 @@ impl Turret {
 pub struct Enemy;
 
 pub const TURRET_DAMAGE: u32 = 1;
+/// Turrets only shoot at players closer than this.
+pub const TURRET_RANGE: f32 = 15.0;
+/// Cosine of the largest angle between a turret's direction and a player it shoots at.
+pub const TURRET_CONE_COS: f32 = 0.707;
+
+/// Sent whenever a turret hits a player, so `VisualsPlugin` can draw the shot.
+#[derive(Message)]
+pub struct TurretFired {
+    pub from: Vec3,
+    pub to: Vec3,
+}
 
 fn turret_shooting_system(
     time: Res<Time>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    turret_query: Query<&Turret>,
-    mut target_query: Query<(&PlayerID, &Transform, &mut Hp, Option<&User>)>,
+    turret_query: Query<(Entity, &Transform, &Turret)>,
+    mut target_query: Query<(
+        &PlayerID,
+        &Transform,
+        &mut Hp,
+        &mut DamageHistory,
+        Option<&User>,
+    )>,
     mut next_state: ResMut<NextState<GameState>>,
     mut match_log: ResMut<MatchLog>,
-    mut gizmos: Gizmos,
+    mut turret_fired: MessageWriter<TurretFired>,
 ) {
     let current_time = time.elapsed_secs();
 
     for (turret_entity, turret_transform, turret) in turret_query.iter() {
         if !turret.is_active(current_time) {
-            // Visual indicator for cooldown (e.g., red box on top)
-            // We can spawn a temporary marker or change material color.
-            // For simplicity, let's draw a red gizmo box above it.
-            gizmos.cube(
-                Transform::from_translation(turret_transform.translation + Vec3::Y * 2.5)
-                    .with_scale(Vec3::splat(0.5)),
-                Color::srgb(1.0, 0.0, 0.0),
-            );
             continue;
-        } else {
-            // Visual indicator for ready (green box)
-            gizmos.cube(
-                Transform::from_translation(turret_transform.translation + Vec3::Y * 2.5)
-                    .with_scale(Vec3::splat(0.5)),
-                Color::srgb(0.0, 1.0, 0.0),
-            );
         }
 
         let turret_pos = turret_transform.translation;
@@ -142,7 +205,7 @@ fn turret_shooting_system(
         let mut closest_target: Option<PlayerID> = None;
         let mut closest_distance = f32::MAX;
 
-        for (target_id, target_transform, _, _) in target_query.iter() {
+        for (target_id, target_transform, _, _, _) in target_query.iter() {
             // Turrets don't have PlayerIDs, their owners do. We need to check against the owner.
             if *target_id == turret.owner {
                 continue;
@@ -152,11 +215,11 @@ fn turret_shooting_system(
             let to_target = target_pos - turret_pos;
             let distance = to_target.length();
 
-            if distance < 15.0 {
+            if distance < TURRET_RANGE {
                 let target_dir = to_target.normalize();
                 let dot = target_dir.dot(direction_vec);
 
-                if dot > 0.707 {
+                if dot > TURRET_CONE_COS {
                     if distance < closest_distance {
                         closest_distance = distance;
                         closest_target = Some(*target_id);
@@ -172,21 +235,22 @@ fn turret_shooting_system(
                 last_shot: current_time,
             });
 
-            if let Ok((_, target_transform, mut hp, user)) = target_query.get_mut(
-                target_query
-                    .iter()
-                    .find(|(id, _, _, _)| **id == target_id)
-                    .unwrap()
-                    .0,
-            ) {
+            if let Ok((_, target_transform, mut hp, mut damage_history, user)) = target_query
+                .get_mut(
+                    target_query
+                        .iter()
+                        .find(|(id, _, _, _, _)| **id == target_id)
+                        .unwrap()
+                        .0,
+                )
+            {
                 hp.take_damage(TURRET_DAMAGE);
+                damage_history.record(turret.owner, TURRET_DAMAGE, current_time);
 
-                let barrel_pos = turret_pos + Vec3::Y * 1.5;
-                gizmos.line(
-                    barrel_pos,
-                    target_transform.translation,
-                    Color::srgb(1.0, 1.0, 0.0),
-                );
+                turret_fired.write(TurretFired {
+                    from: turret_pos + Vec3::Y * 1.5,
+                    to: target_transform.translation,
+                });
 
                 match_log.add(GameEvent::DamageDealt {
                     attacker: turret.owner,
@@ -213,9 +277,9 @@ fn turret_shooting_system(
                         // Despawn the entity associated with the PlayerID
                         if let Some(entity_to_despawn) = target_query
                             .iter()
-                            .find(|(id, _, _, _)| **id == target_id)
-                            .map(|(_, _, _, _)| {
-                                let (p_id, _, _, _) = target_query.get_mut(target_id).unwrap();
+                            .find(|(id, _, _, _, _)| **id == target_id)
+                            .map(|(_, _, _, _, _)| {
+                                let (p_id, _, _, _, _) = target_query.get_mut(target_id).unwrap();
                                 p_id
                             })
                         {
@@ -227,3 +291,34 @@ fn turret_shooting_system(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_count_within_the_window() {
+        let mut history = DamageHistory::default();
+        assert!(!history.hit_within(0.0, 3.0));
+
+        history.record(PlayerID(1), 1, 10.0);
+        assert!(history.hit_within(10.0, 3.0));
+        assert!(history.hit_within(13.0, 3.0));
+        assert!(!history.hit_within(13.5, 3.0));
+
+        // Only the latest hit matters.
+        history.record(PlayerID(1), 1, 20.0);
+        assert!(history.hit_within(22.0, 3.0));
+    }
+
+    #[test]
+    fn old_hits_are_forgotten() {
+        let mut history = DamageHistory::default();
+        history.record(PlayerID(1), 1, 0.0);
+        history.record(PlayerID(2), 1, 10.0);
+        history.record(PlayerID(2), 1, DAMAGE_HISTORY_SECONDS + 5.0);
+
+        let times: Vec<f32> = history.hits.iter().map(|hit| hit.time).collect();
+        assert_eq!(times, vec![10.0, DAMAGE_HISTORY_SECONDS + 5.0]);
+    }
+}
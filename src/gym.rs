@@ -0,0 +1,250 @@
+//! Gym-style environment for training external agents on the real game rules.
+//!
+//! ```ignore
+//! let mut env = Env::new(EnvConfig::default());
+//! let mut observations = env.reset(seed);
+//! loop {
+//!     let actions = policy(&observations);
+//!     let (next, rewards, done) = env.step(&actions);
+//!     observations = next;
+//!     if done {
+//!         break;
+//!     }
+//! }
+//! ```
+//!
+//! Side 0 is always an agent. Side 1 is either an AI running `EnvConfig::opponent` or a
+//! second agent. Agents act through `ExternalControl`, so their actions go through the same
+//! code paths as the rule-based AI.
+
+use crate::ai::rules::{Action, RuleSet};
+use crate::ai::ExternalControl;
+use crate::combat::Hp;
+use crate::headless::{HeadlessMatch, MatchOutcome, DEFAULT_MAX_DURATION};
+use crate::logging::{GameEvent, MatchLog};
+use crate::player::{Inventory, MovementController, PlayerStatus};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+pub struct EnvConfig {
+    /// Rule set of side 1. `None` makes side 1 a second agent.
+    pub opponent: Option<RuleSet>,
+    /// Gameplay ticks simulated per `step`.
+    pub ticks_per_step: u32,
+    /// Simulated seconds after which an episode ends in a draw.
+    pub max_duration: f32,
+    /// Reward per point of damage dealt, and penalty per point taken.
+    pub damage_reward: f32,
+    /// Reward for winning, and penalty for losing, given on the last step.
+    pub win_reward: f32,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        Self {
+            opponent: Some(RuleSet::default()),
+            ticks_per_step: 4,
+            max_duration: DEFAULT_MAX_DURATION,
+            damage_reward: 0.1,
+            win_reward: 1.0,
+        }
+    }
+}
+
+/// What an agent does during one step.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AgentAction {
+    /// Executed once at the start of the step, like a matching rule would be.
+    Rule(Action),
+    /// Raw `MovementController` input, held for the whole step. `direction` is local to the
+    /// player, `rotation` is applied on the first tick.
+    Move { direction: Vec3, rotation: f32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Observation {
+    pub status: PlayerStatus,
+    pub position: Vec3,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub obstacles: u32,
+    pub turrets: u32,
+}
+
+pub struct Env {
+    config: EnvConfig,
+    game: Option<HeadlessMatch>,
+    /// `MatchLog` events before this one were already turned into rewards.
+    rewarded_seq: u64,
+    /// Last observation per agent, repeated once the agent is eliminated.
+    observations: Vec<Observation>,
+}
+
+impl Env {
+    pub fn new(config: EnvConfig) -> Self {
+        Self {
+            config,
+            game: None,
+            rewarded_seq: 0,
+            observations: Vec::new(),
+        }
+    }
+
+    /// Number of sides under external control, and so the length of every action slice.
+    pub fn agent_count(&self) -> usize {
+        if self.config.opponent.is_some() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Starts a new episode and returns the first observation of every agent.
+    pub fn reset(&mut self, seed: u64) -> Vec<Observation> {
+        // Without an opponent, side 1 keeps an empty rule set under external control.
+        let opponent = self.config.opponent.clone().unwrap_or_else(RuleSet::empty);
+        let mut game = HeadlessMatch::new(seed, [RuleSet::empty(), opponent]);
+
+        let entities = game.entities();
+        for entity in &entities[..self.agent_count()] {
+            game.app_mut()
+                .world_mut()
+                .entity_mut(*entity)
+                .insert(ExternalControl::default());
+        }
+
+        self.game = Some(game);
+        self.rewarded_seq = 0;
+        self.observations = Vec::new();
+        self.observe()
+    }
+
+    /// Applies one action per agent, simulates `ticks_per_step` ticks and returns the new
+    /// observations, the reward of every agent and whether the episode is over.
+    pub fn step(&mut self, actions: &[AgentAction]) -> (Vec<Observation>, Vec<f32>, bool) {
+        assert_eq!(
+            actions.len(),
+            self.agent_count(),
+            "one action per agent expected"
+        );
+        let game = self.game.as_mut().expect("Env::reset must be called first");
+
+        let entities = game.entities();
+        let world = game.app_mut().world_mut();
+        for (entity, action) in entities.iter().zip(actions) {
+            // Eliminated players are despawned.
+            let Ok(mut entity) = world.get_entity_mut(*entity) else {
+                continue;
+            };
+            match action {
+                AgentAction::Rule(action) => {
+                    if let Some(mut control) = entity.get_mut::<ExternalControl>() {
+                        control.action = Some(action.clone());
+                        control.manual_movement = false;
+                    }
+                }
+                AgentAction::Move {
+                    direction,
+                    rotation,
+                } => {
+                    if let Some(mut control) = entity.get_mut::<ExternalControl>() {
+                        control.manual_movement = true;
+                    }
+                    if let Some(mut controller) = entity.get_mut::<MovementController>() {
+                        controller.input_direction = *direction;
+                        controller.rotation_delta = *rotation;
+                    }
+                }
+            }
+        }
+
+        for _ in 0..self.config.ticks_per_step {
+            game.step();
+            if game.outcome().is_some() {
+                break;
+            }
+        }
+
+        let outcome = game.outcome();
+        let done = outcome.is_some() || game.elapsed() >= self.config.max_duration;
+        let rewards = self.rewards(outcome);
+        (self.observe(), rewards, done)
+    }
+
+    fn rewards(&mut self, outcome: Option<MatchOutcome>) -> Vec<f32> {
+        let game = self.game.as_ref().expect("Env::reset must be called first");
+        let players = game.players();
+        let mut rewards = vec![0.0; self.agent_count()];
+
+        let match_log = game.app().world().resource::<MatchLog>();
+        for event in match_log.events_since(self.rewarded_seq) {
+            if let GameEvent::DamageDealt {
+                attacker,
+                victim,
+                amount,
+                ..
+            } = event
+            {
+                for (side, reward) in rewards.iter_mut().enumerate() {
+                    if *attacker == players[side] {
+                        *reward += self.config.damage_reward * *amount as f32;
+                    }
+                    if *victim == players[side] {
+                        *reward -= self.config.damage_reward * *amount as f32;
+                    }
+                }
+            }
+        }
+        self.rewarded_seq = match_log.next_seq();
+
+        if let Some(MatchOutcome::Win(winner)) = outcome {
+            for (side, reward) in rewards.iter_mut().enumerate() {
+                *reward += if side == winner {
+                    self.config.win_reward
+                } else {
+                    -self.config.win_reward
+                };
+            }
+        }
+        rewards
+    }
+
+    fn observe(&mut self) -> Vec<Observation> {
+        let game = self.game.as_ref().expect("Env::reset must be called first");
+        let world = game.app().world();
+
+        for (side, entity) in game.entities()[..self.agent_count()].iter().enumerate() {
+            let Ok(entity) = world.get_entity(*entity) else {
+                // Keep the last observation, with the player dead.
+                if let Some(observation) = self.observations.get_mut(side) {
+                    observation.hp = 0;
+                }
+                continue;
+            };
+            let (Some(status), Some(transform), Some(hp), Some(inventory)) = (
+                entity.get::<PlayerStatus>(),
+                entity.get::<Transform>(),
+                entity.get::<Hp>(),
+                entity.get::<Inventory>(),
+            ) else {
+                continue;
+            };
+
+            let observation = Observation {
+                status: status.clone(),
+                position: transform.translation,
+                hp: hp.current,
+                max_hp: hp.max,
+                obstacles: inventory.obstacles,
+                turrets: inventory.turrets,
+            };
+            if side < self.observations.len() {
+                self.observations[side] = observation;
+            } else {
+                self.observations.push(observation);
+            }
+        }
+        self.observations.clone()
+    }
+}
@@ -0,0 +1,216 @@
+//! Headless AI-vs-AI matches, stepped by hand one gameplay tick at a time.
+//!
+//! Used by the tools in `src/bin` to compare rule sets. A match only depends on its seed and
+//! the two rule sets, so every result can be replayed.
+
+use crate::ai::rules::RuleSet;
+use crate::ai::{AiPlayer, AiPlugin, AiRuleSet, PathFollower, TargetDestination};
+use crate::arena::areas::AreaMap;
+use crate::arena::{ArenaConfig, ArenaDescription, ArenaPlugin, SpawnPoints};
+use crate::combat::{CombatPlugin, Hp};
+use crate::logging::{GameEvent, LoggingPlugin, MatchLog};
+use crate::player::{Inventory, MovementController, Player, PlayerPlugin, PlayerStatus};
+use crate::player_id::PlayerID;
+use crate::simulation::{SimRng, SimulationPlugin, TICK_RATE};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::time::TimeUpdateStrategy;
+use std::time::Duration;
+
+/// Builds the `AreaMap` of an arena, including the connectivity computed at startup.
+pub fn arena_area_map(description: ArenaDescription) -> AreaMap {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(ArenaPlugin::new(description));
+    app.update();
+    app.world_mut()
+        .remove_resource::<AreaMap>()
+        .expect("ArenaPlugin inserts the AreaMap")
+}
+
+/// Seconds after which a match without elimination ends in a draw.
+pub const DEFAULT_MAX_DURATION: f32 = 180.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOutcome {
+    /// Index of the winning side.
+    Win(usize),
+    Draw,
+}
+
+impl MatchOutcome {
+    /// 1.0 for a win of `side`, 0.5 for a draw and 0.0 for a loss.
+    pub fn score(&self, side: usize) -> f64 {
+        match self {
+            MatchOutcome::Win(winner) if *winner == side => 1.0,
+            MatchOutcome::Win(_) => 0.0,
+            MatchOutcome::Draw => 0.5,
+        }
+    }
+}
+
+pub struct MatchResult {
+    pub outcome: MatchOutcome,
+    /// Simulated seconds until the match ended.
+    pub duration: f32,
+    pub match_log: MatchLog,
+}
+
+/// Two AI players, one per rule set, on the default arena.
+pub struct HeadlessMatch {
+    app: App,
+    players: [PlayerID; 2],
+    entities: [Entity; 2],
+    /// `MatchLog` events before this one were already checked for eliminations.
+    checked_seq: u64,
+    outcome: Option<MatchOutcome>,
+}
+
+impl HeadlessMatch {
+    pub fn new(seed: u64, rule_sets: [RuleSet; 2]) -> Self {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin))
+            // Every update advances the clock by exactly one gameplay tick.
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+                1.0 / TICK_RATE,
+            )))
+            .init_state::<GameState>()
+            .add_plugins(SimulationPlugin::new(seed))
+            .add_plugins(ArenaPlugin::new(ArenaDescription::default()))
+            .add_plugins(PlayerPlugin)
+            .add_plugins(AiPlugin)
+            .add_plugins(CombatPlugin)
+            .add_plugins(LoggingPlugin);
+        app.finish();
+        app.cleanup();
+
+        let world = app.world_mut();
+        let spawn_points = world.resource::<SpawnPoints>();
+        let spawns = [spawn_points.player, spawn_points.enemy];
+        let tile_size = world.resource::<ArenaConfig>().tile_size;
+
+        let mut rng = world.resource_mut::<SimRng>();
+        let players = [PlayerID::random(&mut rng.0), PlayerID::random(&mut rng.0)];
+
+        let mut entities = [Entity::PLACEHOLDER; 2];
+        for (side, rule_set) in rule_sets.into_iter().enumerate() {
+            // Head for the opponent's spawn until the rules say otherwise.
+            let opponent_spawn = spawns[1 - side];
+            entities[side] = world
+                .spawn((
+                    AiPlayer,
+                    Player,
+                    players[side],
+                    Name::new(format!("Side {}", side)),
+                    PlayerStatus::default(),
+                    Inventory {
+                        obstacles: 6,
+                        turrets: 4,
+                    },
+                    MovementController::default(),
+                    PathFollower::default(),
+                    TargetDestination {
+                        x: (opponent_spawn.x / tile_size - 0.5).floor() as u32,
+                        y: (opponent_spawn.z / tile_size - 0.5).floor() as u32,
+                    },
+                    AiRuleSet(rule_set),
+                    Hp::new(3),
+                    Transform::from_translation(spawns[side]),
+                ))
+                .id();
+        }
+
+        Self {
+            app,
+            players,
+            entities,
+            checked_seq: 0,
+            outcome: None,
+        }
+    }
+
+    pub fn players(&self) -> [PlayerID; 2] {
+        self.players
+    }
+
+    /// Player entity of each side. Eliminated players are despawned.
+    pub fn entities(&self) -> [Entity; 2] {
+        self.entities
+    }
+
+    pub fn app(&self) -> &App {
+        &self.app
+    }
+
+    pub fn app_mut(&mut self) -> &mut App {
+        &mut self.app
+    }
+
+    /// Simulated seconds since the start of the match.
+    pub fn elapsed(&self) -> f32 {
+        self.app.world().resource::<Time<Fixed>>().elapsed_secs()
+    }
+
+    pub fn outcome(&self) -> Option<MatchOutcome> {
+        self.outcome
+    }
+
+    /// Advances the match by one gameplay tick.
+    pub fn step(&mut self) {
+        self.app.update();
+
+        let match_log = self.app.world().resource::<MatchLog>();
+        let mut eliminated = [false; 2];
+        for event in match_log.events_since(self.checked_seq) {
+            if let GameEvent::PlayerEliminated { entity, .. } = event {
+                for (side, player) in self.players.iter().enumerate() {
+                    eliminated[side] |= entity == player;
+                }
+            }
+        }
+        self.checked_seq = match_log.next_seq();
+
+        self.outcome = match eliminated {
+            [false, false] => self.outcome,
+            [true, false] => Some(MatchOutcome::Win(1)),
+            [false, true] => Some(MatchOutcome::Win(0)),
+            [true, true] => Some(MatchOutcome::Draw),
+        };
+    }
+
+    /// Steps until one side is eliminated or `max_duration` seconds have passed.
+    pub fn run(mut self, max_duration: f32) -> MatchResult {
+        while self.outcome.is_none() && self.elapsed() < max_duration {
+            self.step();
+        }
+
+        MatchResult {
+            outcome: self.outcome.unwrap_or(MatchOutcome::Draw),
+            duration: self.elapsed(),
+            match_log: self
+                .app
+                .world_mut()
+                .remove_resource::<MatchLog>()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay(seed: u64) -> String {
+        let rule_sets = [RuleSet::default(), RuleSet::new_turret_only()];
+        let result = HeadlessMatch::new(seed, rule_sets).run(20.0);
+        serde_json::to_string(&result.match_log).expect("match logs serialize")
+    }
+
+    #[test]
+    fn same_seed_reproduces_the_match_log() {
+        let first = replay(42);
+        assert!(first.contains("AiDecision"), "nothing happened: {}", first);
+        assert_eq!(first, replay(42));
+    }
+}
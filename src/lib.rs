@@ -1,22 +1,373 @@
 //! # Bevy Test Shared Library
 //!
-//! This crate defines only the top-level WebSocket message enums.
+//! This crate defines the top-level WebSocket message enums and the protocol handshake types.
 //! All other shared types are defined in their respective modules within the client codebase.
+//! The game modules live here too, so the tools in `src/bin` can run the simulation.
 
+// Lets the game modules refer to the shared types as `bevy_test::...`, like the binaries do.
+extern crate self as bevy_test;
+
+pub mod ai;
+pub mod arena;
+pub mod building;
+pub mod combat;
+pub mod gym;
+pub mod headless;
+pub mod logging;
+pub mod network;
+pub mod pathfinding;
+pub mod player;
+pub mod player_id;
+pub mod simulation;
+pub mod user;
+pub mod visuals;
+
+use bevy::prelude::{States, Vec3};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 // Re-export the necessary types for the message enums.
-use crate::ai::rules::RuleSet;
-use crate::logging::MatchLog;
-use crate::player::PlayerStatus;
+pub use crate::ai::behavior::{BehaviorTree, Node};
+use crate::ai::rules::Score;
+pub use crate::ai::rules::{Action, Condition, RuleSet};
+pub use crate::logging::{GameEvent, MatchLog};
+pub use crate::player::PlayerStatus;
+pub use crate::player_id::PlayerID;
+
+#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum GameState {
+    #[default]
+    Playing,
+    GameOver,
+}
+
+// Note: These are also defined in player.rs for now.
+// Ideally, we should move them to a shared config resource.
+pub const PLAYER_SIZE: Vec3 = Vec3::new(1.0, 3.0, 1.0);
+
+/// Bump whenever a message, `RuleSet`, `BehaviorTree`, `Condition`, `Action`, `Score` or
+/// `GameEvent` changes shape.
+pub const PROTOCOL_VERSION: u32 = 13;
+
+/// Build of the game sending the `Hello`.
+pub const GAME_BUILD: &str = env!("CARGO_PKG_VERSION");
+
+/// Rule DSL variants one side of the connection understands.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub conditions: Vec<String>,
+    pub actions: Vec<String>,
+    /// Missing from builds older than utility scoring.
+    #[serde(default)]
+    pub scores: Vec<String>,
+    /// Behavior tree nodes, missing from builds older than behavior trees.
+    #[serde(default)]
+    pub nodes: Vec<String>,
+}
+
+impl Capabilities {
+    /// Everything this build of the game supports.
+    pub fn current() -> Self {
+        Self {
+            conditions: Condition::VARIANTS.iter().map(|v| v.to_string()).collect(),
+            actions: Action::VARIANTS.iter().map(|v| v.to_string()).collect(),
+            scores: Score::VARIANTS.iter().map(|v| v.to_string()).collect(),
+            nodes: Node::VARIANTS.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    /// Checks that every condition and action in `rule_set` is supported.
+    pub fn check_rule_set(&self, rule_set: &RuleSet) -> Result<(), ProtocolError> {
+        for rule in &rule_set.rules {
+            let mut unsupported = None;
+            rule.visit_conditions(&mut |condition| {
+                let name = condition.variant_name();
+                if unsupported.is_none() && !self.conditions.iter().any(|c| c == name) {
+                    unsupported = Some(name);
+                }
+            });
+
+            rule.action.visit(&mut |action| {
+                let name = action.variant_name();
+                if unsupported.is_none() && !self.actions.iter().any(|a| a == name) {
+                    unsupported = Some(name);
+                }
+            });
+
+            if let Some(score) = &rule.score {
+                score.visit(&mut |score| {
+                    let name = score.variant_name();
+                    if unsupported.is_none() && !self.scores.iter().any(|s| s == name) {
+                        unsupported = Some(name);
+                    }
+                });
+            }
+
+            if let Some(variant) = unsupported {
+                return Err(ProtocolError::UnknownVariant {
+                    variant: variant.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every node, condition and action in `tree` is supported.
+    pub fn check_behavior_tree(&self, tree: &BehaviorTree) -> Result<(), ProtocolError> {
+        let mut unsupported = None;
+        tree.root.visit(&mut |_, node| {
+            let name = node.variant_name();
+            if unsupported.is_none() && !self.nodes.iter().any(|n| n == name) {
+                unsupported = Some(name);
+            }
+            match node {
+                Node::Condition(condition) => condition.visit(&mut |condition| {
+                    let name = condition.variant_name();
+                    if unsupported.is_none() && !self.conditions.iter().any(|c| c == name) {
+                        unsupported = Some(name);
+                    }
+                }),
+                Node::Action(action) => action.visit(&mut |action| {
+                    let name = action.variant_name();
+                    if unsupported.is_none() && !self.actions.iter().any(|a| a == name) {
+                        unsupported = Some(name);
+                    }
+                }),
+                _ => {}
+            }
+        });
+
+        match unsupported {
+            Some(variant) => Err(ProtocolError::UnknownVariant {
+                variant: variant.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Which AI players a rule set update is meant for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RuleSetTarget {
+    All,
+    Players(Vec<PlayerID>),
+}
+
+impl RuleSetTarget {
+    pub fn includes(&self, player: &PlayerID) -> bool {
+        match self {
+            RuleSetTarget::All => true,
+            RuleSetTarget::Players(players) => players.contains(player),
+        }
+    }
+}
+
+/// Structured reason for rejecting a message, sent back to the peer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ProtocolError {
+    VersionMismatch {
+        expected: u32,
+        received: u32,
+    },
+    UnknownVariant {
+        variant: String,
+    },
+    /// A `HasItem` condition names something that is not an inventory item.
+    UnknownItem {
+        item: String,
+    },
+    /// A rule set failed `RuleSet::validate`, one entry per error.
+    InvalidRuleSet {
+        errors: Vec<String>,
+    },
+    /// A behavior tree failed `BehaviorTree::validate`, one entry per error.
+    InvalidBehaviorTree {
+        errors: Vec<String>,
+    },
+    HandshakeRequired,
+    Malformed {
+        reason: String,
+    },
+}
+
+impl ProtocolError {
+    fn from_serde(error: serde_json::Error) -> Self {
+        // serde reports unknown enum tags as "unknown variant `Name`, expected ...".
+        let reason = error.to_string();
+        if let Some(rest) = reason.strip_prefix(crate::ai::rules::UNKNOWN_ITEM_ERROR) {
+            if let Some((item, _)) = rest.split_once('`') {
+                return ProtocolError::UnknownItem {
+                    item: item.to_string(),
+                };
+            }
+        }
+        if let Some(rest) = reason.strip_prefix("unknown variant `") {
+            if let Some((variant, _)) = rest.split_once('`') {
+                return ProtocolError::UnknownVariant {
+                    variant: variant.to_string(),
+                };
+            }
+        }
+        ProtocolError::Malformed { reason }
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::VersionMismatch { expected, received } => write!(
+                f,
+                "protocol version mismatch: expected {}, received {}",
+                expected, received
+            ),
+            ProtocolError::UnknownVariant { variant } => {
+                write!(f, "unknown variant `{}`", variant)
+            }
+            ProtocolError::UnknownItem { item } => {
+                let expected: Vec<&str> = crate::ai::rules::ITEMS
+                    .iter()
+                    .map(|(_, name)| *name)
+                    .collect();
+                write!(
+                    f,
+                    "unknown item `{}` in HasItem, expected one of: {}",
+                    item,
+                    expected.join(", ")
+                )
+            }
+            ProtocolError::InvalidRuleSet { errors } => {
+                write!(f, "invalid rule set: {}", errors.join("; "))
+            }
+            ProtocolError::InvalidBehaviorTree { errors } => {
+                write!(f, "invalid behavior tree: {}", errors.join("; "))
+            }
+            ProtocolError::HandshakeRequired => write!(f, "handshake required"),
+            ProtocolError::Malformed { reason } => write!(f, "malformed message: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClientMessage {
-    UpdatePlayerStatus(PlayerStatus),
-    PushMatchLog(MatchLog),
+    /// First message on every connection. `session` stays the same across reconnects.
+    Hello {
+        protocol_version: u32,
+        game_build: String,
+        capabilities: Capabilities,
+        session: u64,
+    },
+    UpdatePlayerStatus {
+        player: PlayerID,
+        status: PlayerStatus,
+    },
+    /// `MatchLog` events numbered from `from_seq` on.
+    PushMatchEvents {
+        from_seq: u64,
+        events: Vec<GameEvent>,
+    },
+    /// Acknowledges an `UpdateRuleSet` or `UpdateBehaviorTree`, listing the players that
+    /// switched to it.
+    RuleSetApplied {
+        update_id: u64,
+        applied_to: Vec<PlayerID>,
+        time: f32,
+    },
+    /// The client could not accept a server message.
+    ProtocolError(ProtocolError),
+}
+
+impl ClientMessage {
+    pub fn decode(text: &str) -> Result<Self, ProtocolError> {
+        serde_json::from_str(text).map_err(ProtocolError::from_serde)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ServerMessage {
-    UpdateRuleSet(RuleSet),
+    /// Reply to a compatible `Hello`, with the number of match events the server already
+    /// has for the session.
+    Welcome {
+        protocol_version: u32,
+        server_build: String,
+        next_event_seq: u64,
+    },
+    /// All match events before `next_seq` have been received.
+    MatchEventsAck { next_seq: u64 },
+    /// The server could not accept a client message.
+    Rejected(ProtocolError),
+    UpdateRuleSet {
+        update_id: u64,
+        target: RuleSetTarget,
+        rule_set: RuleSet,
+    },
+    /// Replaces the rule set of the targeted AI players with a behavior tree, until the next
+    /// `UpdateRuleSet` for them.
+    UpdateBehaviorTree {
+        update_id: u64,
+        target: RuleSetTarget,
+        tree: BehaviorTree,
+    },
+}
+
+impl ServerMessage {
+    pub fn decode(text: &str) -> Result<Self, ProtocolError> {
+        serde_json::from_str(text).map_err(ProtocolError::from_serde)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An `UpdateRuleSet` with a single rule, as the server would send it.
+    fn update_rule_set(condition: &str, action: &str) -> String {
+        format!(
+            r#"{{"UpdateRuleSet":{{"update_id":0,"target":"All","rule_set":{{"rules":[
+                {{"name":"R","priority":1,"condition":{},"action":{}}}]}}}}}}"#,
+            condition, action
+        )
+    }
+
+    // `from_serde` relies on the wording of serde's errors, pinned down here.
+
+    #[test]
+    fn valid_rule_set_decodes() {
+        let text = update_rule_set(r#""IsEnemyVisible""#, r#""ChaseEnemy""#);
+        assert!(ServerMessage::decode(&text).is_ok());
+    }
+
+    #[test]
+    fn unknown_condition_is_an_unknown_variant() {
+        let text = update_rule_set(r#"{"IsTeleporting":{"range":3}}"#, r#""Idle""#);
+        assert_eq!(
+            ServerMessage::decode(&text).unwrap_err(),
+            ProtocolError::UnknownVariant {
+                variant: "IsTeleporting".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_action_is_an_unknown_variant() {
+        let text = update_rule_set(r#""True""#, r#""Teleport""#);
+        assert_eq!(
+            ServerMessage::decode(&text).unwrap_err(),
+            ProtocolError::UnknownVariant {
+                variant: "Teleport".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_item_is_an_unknown_item() {
+        let text = update_rule_set(r#"{"HasItem":{"item":"Gold","count":1}}"#, r#""Idle""#);
+        assert_eq!(
+            ServerMessage::decode(&text).unwrap_err(),
+            ProtocolError::UnknownItem {
+                item: "Gold".to_string()
+            }
+        );
+    }
 }
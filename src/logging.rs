@@ -1,3 +1,4 @@
+use crate::ai::trace::RuleTrace;
 use crate::arena::areas::AreaID;
 use crate::arena::CollectibleType;
 use crate::building::StructureType;
@@ -46,10 +47,15 @@ pub enum GameEvent {
         entity_name: String,
         rule_name: String,
         condition_met: bool,
+        /// Utility of the rule under `Strategy::Utility`, if its condition holds.
+        score: Option<f32>,
         inventory_obstacles: u32,
         inventory_turrets: u32,
         visible_enemies: usize,
         time: f32,
+        /// How the rule was checked, with `DecisionTracing::Attach`.
+        #[serde(default)]
+        trace: Option<RuleTrace>,
     },
 }
 
@@ -62,6 +68,28 @@ impl MatchLog {
     pub fn add(&mut self, event: GameEvent) {
         self.events.push(event);
     }
+
+    /// Sequence number the next event will get. Events are numbered by their index.
+    pub fn next_seq(&self) -> u64 {
+        self.events.len() as u64
+    }
+
+    pub fn events_since(&self, seq: u64) -> &[GameEvent] {
+        self.events.get(seq as usize..).unwrap_or(&[])
+    }
+
+    /// Appends events numbered from `from_seq` on, skipping the ones already in the log.
+    /// Returns `false` if the chunk starts past the end of the log, leaving a gap.
+    pub fn append_from(&mut self, from_seq: u64, events: &[GameEvent]) -> bool {
+        let next_seq = self.next_seq();
+        if from_seq > next_seq {
+            return false;
+        }
+        let already_known = (next_seq - from_seq) as usize;
+        self.events
+            .extend(events.iter().skip(already_known).cloned());
+        true
+    }
 }
 
 pub struct LoggingPlugin;
@@ -71,3 +99,69 @@ impl Plugin for LoggingPlugin {
         app.init_resource::<MatchLog>();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An event told apart by its time.
+    fn event(time: f32) -> GameEvent {
+        GameEvent::StructureDestroyed {
+            destroyer: None,
+            structure: StructureType::Obstacle,
+            location: (0, 0),
+            time,
+        }
+    }
+
+    fn times(events: &[GameEvent]) -> Vec<f32> {
+        events
+            .iter()
+            .map(|event| match event {
+                GameEvent::StructureDestroyed { time, .. } => *time,
+                _ => unreachable!("tests only log `event`s"),
+            })
+            .collect()
+    }
+
+    fn log(count: usize) -> MatchLog {
+        let mut log = MatchLog::default();
+        for seq in 0..count {
+            log.add(event(seq as f32));
+        }
+        log
+    }
+
+    #[test]
+    fn events_since_starts_at_the_cursor() {
+        let log = log(3);
+        assert_eq!(times(log.events_since(0)), vec![0.0, 1.0, 2.0]);
+        assert_eq!(times(log.events_since(2)), vec![2.0]);
+        assert!(log.events_since(3).is_empty());
+        assert!(log.events_since(10).is_empty());
+    }
+
+    #[test]
+    fn append_from_skips_known_events() {
+        let mut copy = log(2);
+        let original = log(4);
+
+        // Overlaps the copy by one event.
+        assert!(copy.append_from(1, original.events_since(1)));
+        assert_eq!(times(&copy.events), vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(copy.next_seq(), 4);
+
+        // Nothing new.
+        assert!(copy.append_from(4, &[]));
+        assert!(copy.append_from(2, original.events_since(2)));
+        assert_eq!(copy.next_seq(), 4);
+    }
+
+    #[test]
+    fn append_from_refuses_gaps() {
+        let mut copy = log(2);
+        let original = log(4);
+        assert!(!copy.append_from(3, original.events_since(3)));
+        assert_eq!(copy.next_seq(), 2);
+    }
+}
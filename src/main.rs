@@ -1,103 +1,80 @@
-mod ai;
-mod arena;
-mod building;
-mod combat;
-mod logging;
-mod pathfinding;
-mod player;
-mod player_id;
-mod user;
-
-use ai::{AiPlayer, AiPlugin, AiRuleSet, PathFollower, TargetDestination};
-use arena::areas::{Area, AreaID};
-use arena::{ArenaConfig, ArenaDescription, ArenaPlugin, SpawnPoints};
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::asset::AssetPlugin;
+use bevy::log::LogPlugin;
 use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
 use bevy::window::{CursorGrabMode, CursorOptions, PrimaryWindow};
-use building::BuildingPlugin;
-use building::StructureType;
-use combat::{CombatPlugin, Enemy, Hp};
-use logging::LoggingPlugin;
-use player::{Inventory, MovementController, Player, PlayerPlugin};
-use user::{MainCamera, SelectedBuildType, User, UserPlugin};
-
-use bevy_test::{PlayerID, PlayerStatus};
-
-#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
-pub enum GameState {
-    #[default]
-    Playing,
-    GameOver,
-}
-
-// --- Game Constants ---
-
-// Note: These are also defined in player.rs for now.
-// Ideally, we should move them to a shared config resource.
-const PLAYER_SIZE: Vec3 = Vec3::new(1.0, 3.0, 1.0);
-
-// . = Floor
-// X = Wall
-// O = Obstacle
-// T = Turret Resource
-// B = Block (Obstacle) Resource
-const ARENA_LAYOUT: &str = "
-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
-X........X.............................X
-X...T....X.............................X
-X........X.............................X
-X...XXXXXX.......XXXXXXXXXXXXXX....XXXXX
-X...XXXXXX.......XXXXXXXXXXXXXX....XXXXX
-X......................................X
-X......................................X
-X......................................X
-X......................................X
-X......................................X
-X......................................X
-X...XXXXXXXXXXXX...XXXX...XXXXXXXXXXXX.X
-X...XXXXXXXXXXXX...XXXX...XXXXXXXXXXXX.X
-X..................XXXX................X
-X..................XXXX................X
-X......................................X
-X......................................X
-X......................................X
-X......................................X
-X...XXXXXX.......XXXXXXXXXXXXXX....XXXXX
-X...XXXXXX.......XXXXXXXXXXXXXX....XXXXX
-X......................................X
-X.............................X........X
-X.............................X....T...X
-X.............................X........X
-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
-";
+use bevy_test::ai::assets::{AiRuleFile, RuleSetAssetPlugin};
+use bevy_test::ai::trace::DecisionTracing;
+use bevy_test::ai::{
+    self, AiPlayer, AiPlugin, AiRuleSet, DecisionLogging, PathFollower, TargetDestination,
+    ThinkRate,
+};
+use bevy_test::arena::{ArenaConfig, ArenaDescription, ArenaPlugin, SpawnPoints};
+use bevy_test::building::{BuildGhost, BuildingPlugin, StructureType};
+use bevy_test::combat::{CombatPlugin, Enemy, Hp};
+use bevy_test::logging::LoggingPlugin;
+use bevy_test::network::{NetworkConfig, NetworkPlugin};
+use bevy_test::player::{Inventory, MovementController, Player, PlayerPlugin};
+use bevy_test::simulation::{SimRng, SimulationPlugin};
+use bevy_test::user::{MainCamera, SelectedBuildType, User, UserPlugin};
+use bevy_test::visuals::VisualsPlugin;
+use bevy_test::{GameState, PlayerID, PlayerStatus};
+use std::time::Duration;
 
 fn main() {
-    let arena_description = ArenaDescription {
-        layout: ARENA_LAYOUT.to_string(),
-        areas: vec![
-            Area::new(AreaID("UserBase".to_string()), 0, 0, 10, 10),
-            Area::new(AreaID("EnemyBase".to_string()), 30, 0, 39, 10),
-            Area::new(AreaID("CenterArena".to_string()), 11, 0, 29, 27),
-            Area::new(AreaID("NorthCorridor".to_string()), 0, 11, 39, 27),
-        ],
-        player_spawn: Vec3::new(18.0, PLAYER_SIZE.y / 2.0, 10.0),
-        ai_spawn: Vec3::new(26.0, PLAYER_SIZE.y / 2.0, 10.0),
-        enemy_spawn: Vec3::new(142.0, PLAYER_SIZE.y / 2.0, 10.0),
-        resource_respawn_time: 30.0,
-    };
-
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .init_state::<GameState>()
+    let arena_description = ArenaDescription::default();
+
+    // `--headless` runs the simulation without window, rendering or user input,
+    // e.g. on a CI box or a training server.
+    let headless = std::env::args().any(|arg| arg == "--headless");
+    // `--seed N` replays a match; the seed of every run is logged at startup.
+    let seed = std::env::args()
+        .skip_while(|arg| arg != "--seed")
+        .nth(1)
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(rand::random);
+    // `--trace-decisions` logs every rule the AIs check instead of only their choices, with
+    // the result of every part of its condition.
+    let trace_decisions = std::env::args().any(|arg| arg == "--trace-decisions");
+
+    let mut app = App::new();
+
+    if headless {
+        app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / 60.0,
+            ))),
+            StatesPlugin,
+            LogPlugin::default(),
+            AssetPlugin::default(),
+        ));
+    } else {
+        app.add_plugins(DefaultPlugins)
+            .add_plugins(VisualsPlugin)
+            .add_plugins(UserPlugin)
+            .add_plugins(BuildingPlugin)
+            .add_systems(Startup, setup_scene)
+            .add_systems(Update, (grab_cursor, debug_log_positions, draw_tile_grid));
+    }
+
+    app.init_state::<GameState>()
+        .add_plugins(SimulationPlugin::new(seed))
         .add_plugins(ArenaPlugin::new(arena_description))
         .add_plugins(PlayerPlugin)
-        .add_plugins(UserPlugin)
         .add_plugins(AiPlugin)
-        .add_plugins(BuildingPlugin)
+        .add_plugins(RuleSetAssetPlugin)
         .add_plugins(CombatPlugin)
         .add_plugins(LoggingPlugin)
-        .add_systems(Startup, setup)
-        .add_systems(Update, (grab_cursor, debug_log_positions, draw_tile_grid))
-        .run();
+        .add_plugins(NetworkPlugin::new(NetworkConfig::default()))
+        .add_systems(Startup, spawn_players);
+
+    if trace_decisions {
+        app.insert_resource(DecisionLogging::FullTrace)
+            .insert_resource(DecisionTracing::Attach);
+    }
+
+    app.run();
 }
 
 fn debug_log_positions(
@@ -116,7 +93,7 @@ fn draw_tile_grid(
     mut gizmos: Gizmos,
     config: Res<ArenaConfig>,
     player_query: Query<&Transform, With<User>>,
-    ghost_query: Query<&Transform, With<crate::building::BuildGhost>>,
+    ghost_query: Query<&Transform, With<BuildGhost>>,
 ) {
     for x in 0..=config.width {
         let world_x = x as f32 * config.tile_size;
@@ -161,17 +138,13 @@ fn grab_cursor(
     }
 }
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    spawn_points: Res<SpawnPoints>,
-) {
+/// Spawns the gameplay side of all players. Meshes are attached by `VisualsPlugin`.
+fn spawn_players(mut commands: Commands, spawn_points: Res<SpawnPoints>, mut rng: ResMut<SimRng>) {
     // Player (User controlled)
     commands.spawn((
         User,
         Player,
-        PlayerID::random(),
+        PlayerID::random(&mut rng.0),
         Name::new("User"),
         PlayerStatus::default(),
         Inventory {
@@ -181,8 +154,6 @@ fn setup(
         MovementController::default(),
         SelectedBuildType(StructureType::Obstacle),
         Hp::new(3),
-        Mesh3d(meshes.add(Cuboid::new(PLAYER_SIZE.x, PLAYER_SIZE.y, PLAYER_SIZE.z))),
-        MeshMaterial3d(materials.add(Color::srgb(0.8, 0.7, 0.6))),
         Transform::from_translation(spawn_points.player),
     ));
 
@@ -198,9 +169,8 @@ fn setup(
         PathFollower::default(),
         TargetDestination { x: 35, y: 25 }, // Go to bottom right (Valid Y)
         AiRuleSet(ai::rules::RuleSet::default()),
+        AiRuleFile("ai/default.rules.json".to_string()),
         Hp::new(3),
-        Mesh3d(meshes.add(Cuboid::new(PLAYER_SIZE.x, PLAYER_SIZE.y, PLAYER_SIZE.z))),
-        MeshMaterial3d(materials.add(Color::srgb(0.2, 0.2, 0.8))), // Blue AI
         Transform::from_translation(spawn_points.ai),
     ));
     */
@@ -210,7 +180,7 @@ fn setup(
         AiPlayer,
         Enemy,
         Player,
-        PlayerID::random(),
+        PlayerID::random(&mut rng.0),
         Name::new("Enemy"),
         PlayerStatus::default(),
         Inventory {
@@ -220,13 +190,16 @@ fn setup(
         MovementController::default(),
         PathFollower::default(),
         TargetDestination { x: 4, y: 2 }, // Go to User's start (Grid 4, 2)
+        // Used until the rule file is loaded, and if it can't be.
         AiRuleSet(ai::rules::RuleSet::new_turret_only()),
+        AiRuleFile("ai/turret_only.rules.json".to_string()),
+        ThinkRate(5.0),
         Hp::new(3),
-        Mesh3d(meshes.add(Cuboid::new(PLAYER_SIZE.x, PLAYER_SIZE.y, PLAYER_SIZE.z))),
-        MeshMaterial3d(materials.add(Color::srgb(0.8, 0.2, 0.2))), // Red Enemy
         Transform::from_translation(spawn_points.enemy),
     ));
+}
 
+fn setup_scene(mut commands: Commands) {
     // Light
     commands.spawn((
         DirectionalLight {
@@ -0,0 +1,593 @@
+//! WebSocket link to the coaching backend.
+//!
+//! Pushes `ClientMessage`s (player status and new match events) to the server and applies
+//! incoming `ServerMessage`s to the AI players. Nothing but the `Hello` is sent before the
+//! server answered with a compatible `Welcome`.
+//!
+//! The game never waits on the server: outgoing messages go into a bounded queue that is
+//! flushed while connected, and a lost connection is retried with exponential backoff. A
+//! server speaking another protocol version is not retried.
+//! Match events are not queued, the `MatchLog` itself is replayed from the last ack.
+
+use crate::ai::behavior::BehaviorTree;
+use crate::ai::rules::RuleSet;
+use crate::ai::validation::{self, Severity};
+use crate::ai::{AiBehaviorTree, AiPlayer, AiRuleSet};
+use crate::arena::areas::AreaMap;
+use crate::logging::MatchLog;
+use crate::player::PlayerStatus;
+use crate::player_id::PlayerID;
+use bevy::prelude::*;
+use bevy_test::{
+    Capabilities, ClientMessage, ProtocolError, RuleSetTarget, ServerMessage, GAME_BUILD,
+    PROTOCOL_VERSION,
+};
+use ewebsock::{WsEvent, WsMessage, WsReceiver, WsSender};
+use std::collections::VecDeque;
+
+/// AI players the server can reconfigure.
+type AiQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Name,
+        &'static PlayerID,
+        &'static mut AiRuleSet,
+    ),
+    With<AiPlayer>,
+>;
+
+pub const DEFAULT_SERVER_URL: &str = "ws://127.0.0.1:9001";
+
+#[derive(Resource, Clone)]
+pub struct NetworkConfig {
+    pub url: String,
+    /// Seconds between two pushes of player status and match log.
+    pub send_interval: f32,
+    /// Messages kept while disconnected; the oldest are dropped first.
+    pub queue_capacity: usize,
+    /// Seconds before the first reconnect attempt, doubled after every failure.
+    pub reconnect_delay: f32,
+    pub max_reconnect_delay: f32,
+    /// Give up and go `Offline` after this many failed attempts. `None` retries forever.
+    pub max_reconnect_attempts: Option<u32>,
+}
+
+impl NetworkConfig {
+    pub fn backoff(&self, attempt: u32) -> f32 {
+        let exponent = attempt.saturating_sub(1).min(16) as i32;
+        (self.reconnect_delay * 2f32.powi(exponent)).min(self.max_reconnect_delay)
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_SERVER_URL.to_string(),
+            send_interval: 1.0,
+            queue_capacity: 256,
+            reconnect_delay: 1.0,
+            max_reconnect_delay: 30.0,
+            max_reconnect_attempts: None,
+        }
+    }
+}
+
+pub struct NetworkPlugin {
+    config: NetworkConfig,
+}
+
+impl NetworkPlugin {
+    pub fn new(config: NetworkConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        let connection = ServerConnection::new(self.config.queue_capacity);
+
+        app.insert_resource(self.config.clone())
+            .insert_resource(connection.state)
+            .insert_non_send_resource(connection)
+            .insert_resource(MatchEventSync {
+                session: rand::random(),
+                acked_seq: 0,
+                sent_seq: 0,
+            })
+            .add_systems(
+                Update,
+                (
+                    maintain_connection,
+                    receive_server_messages,
+                    push_client_updates,
+                    publish_connection_state,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Link to the coaching server, mirrored from the network layer every frame.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Handshake completed, messages are flowing.
+    Connected,
+    /// Not connected; a new attempt is in progress or scheduled.
+    /// `attempt` counts the failures since the last successful handshake.
+    Reconnecting { attempt: u32 },
+    /// Not connected and no longer trying.
+    Offline,
+}
+
+/// Progress of streaming `MatchLog` events to the server.
+#[derive(Resource)]
+pub struct MatchEventSync {
+    /// Identifies this game to the server across reconnects.
+    pub session: u64,
+    /// Every event before this one is known to have reached the server.
+    pub acked_seq: u64,
+    /// Every event before this one has been sent on the current connection.
+    pub sent_seq: u64,
+}
+
+/// WebSocket to the coaching server plus the queue of messages waiting for it.
+/// Non-send resource, since the web backend of `ewebsock` can't leave the main thread.
+pub struct ServerConnection {
+    socket: Option<(WsSender, WsReceiver)>,
+    state: ConnectionState,
+    /// Seconds until the next connection attempt.
+    retry_timer: f32,
+    queue: VecDeque<ClientMessage>,
+    queue_capacity: usize,
+}
+
+impl ServerConnection {
+    fn new(queue_capacity: usize) -> Self {
+        Self {
+            socket: None,
+            state: ConnectionState::Reconnecting { attempt: 0 },
+            retry_timer: 0.0,
+            queue: VecDeque::with_capacity(queue_capacity),
+            queue_capacity,
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Queues a message, sent as soon as the connection is up.
+    pub fn queue(&mut self, message: ClientMessage) {
+        if self.queue.len() >= self.queue_capacity {
+            self.queue.pop_front();
+            warn!("Outgoing message queue full, dropping the oldest message");
+        }
+        self.queue.push_back(message);
+    }
+
+    /// Sends a message right away, or drops it if there is no socket.
+    fn send_now(&mut self, message: &ClientMessage) {
+        let Some((sender, _)) = &mut self.socket else {
+            return;
+        };
+        match serde_json::to_string(message) {
+            Ok(json) => sender.send(WsMessage::Text(json)),
+            Err(e) => warn!("Failed to serialize client message: {}", e),
+        }
+    }
+
+    fn flush(&mut self) {
+        for message in self.take_sendable() {
+            self.send_now(&message);
+        }
+    }
+
+    /// Empties the queue, oldest message first, once the handshake is done.
+    fn take_sendable(&mut self) -> Vec<ClientMessage> {
+        if self.state != ConnectionState::Connected {
+            return Vec::new();
+        }
+        self.queue.drain(..).collect()
+    }
+
+    fn try_recv(&self) -> Option<WsEvent> {
+        self.socket
+            .as_ref()
+            .and_then(|(_, receiver)| receiver.try_recv())
+    }
+
+    fn open(&mut self, config: &NetworkConfig) {
+        match ewebsock::connect(config.url.clone(), ewebsock::Options::default()) {
+            Ok(socket) => {
+                info!("Connecting to coaching server at {}", config.url);
+                self.socket = Some(socket);
+            }
+            Err(e) => {
+                warn!(
+                    "Could not connect to coaching server at {}: {}",
+                    config.url, e
+                );
+                self.lost(config);
+            }
+        }
+    }
+
+    /// Drops the socket and schedules the next attempt, or gives up.
+    fn lost(&mut self, config: &NetworkConfig) {
+        self.socket = None;
+        if self.state == ConnectionState::Offline {
+            return;
+        }
+
+        let attempt = match self.state {
+            ConnectionState::Reconnecting { attempt } => attempt + 1,
+            _ => 1,
+        };
+
+        if config
+            .max_reconnect_attempts
+            .is_some_and(|max| attempt > max)
+        {
+            warn!(
+                "Giving up on the coaching server after {} attempts",
+                attempt - 1
+            );
+            self.state = ConnectionState::Offline;
+            return;
+        }
+
+        self.retry_timer = config.backoff(attempt);
+        self.state = ConnectionState::Reconnecting { attempt };
+        info!(
+            "Reconnecting to coaching server in {:.1}s (attempt {})",
+            self.retry_timer, attempt
+        );
+    }
+
+    /// Drops the socket and stops reconnecting, after an error that retrying won't fix.
+    fn give_up(&mut self, error: &ProtocolError) {
+        warn!("Giving up on the coaching server: {}", error);
+        self.socket = None;
+        self.state = ConnectionState::Offline;
+    }
+}
+
+fn maintain_connection(
+    mut connection: NonSendMut<ServerConnection>,
+    config: Res<NetworkConfig>,
+    time: Res<Time>,
+) {
+    if connection.socket.is_some() || connection.state == ConnectionState::Offline {
+        return;
+    }
+
+    connection.retry_timer -= time.delta_secs();
+    if connection.retry_timer <= 0.0 {
+        connection.open(&config);
+    }
+}
+
+fn receive_server_messages(
+    mut commands: Commands,
+    mut connection: NonSendMut<ServerConnection>,
+    config: Res<NetworkConfig>,
+    mut ai_query: AiQuery,
+    mut event_sync: ResMut<MatchEventSync>,
+    area_map: Res<AreaMap>,
+    // The gameplay clock, as in the `MatchLog`.
+    time: Res<Time<Fixed>>,
+) {
+    while let Some(event) = connection.try_recv() {
+        match event {
+            WsEvent::Opened => {
+                info!("Connected to coaching server, sending handshake");
+                connection.send_now(&ClientMessage::Hello {
+                    protocol_version: PROTOCOL_VERSION,
+                    game_build: GAME_BUILD.to_string(),
+                    capabilities: Capabilities::current(),
+                    session: event_sync.session,
+                });
+            }
+            WsEvent::Message(WsMessage::Text(text)) => match ServerMessage::decode(&text) {
+                Ok(message) => handle_server_message(
+                    message,
+                    &mut connection,
+                    &mut commands,
+                    &mut ai_query,
+                    &mut event_sync,
+                    &area_map,
+                    time.elapsed_secs(),
+                ),
+                Err(error) => {
+                    warn!("Rejected server message: {}", error);
+                    connection.send_now(&ClientMessage::ProtocolError(error));
+                }
+            },
+            WsEvent::Message(_) => {}
+            WsEvent::Error(e) => {
+                warn!("Coaching server error: {}", e);
+                connection.lost(&config);
+            }
+            WsEvent::Closed => {
+                info!("Coaching server closed the connection");
+                connection.lost(&config);
+            }
+        }
+    }
+}
+
+fn handle_server_message(
+    message: ServerMessage,
+    connection: &mut ServerConnection,
+    commands: &mut Commands,
+    ai_query: &mut AiQuery,
+    event_sync: &mut MatchEventSync,
+    area_map: &AreaMap,
+    time: f32,
+) {
+    match message {
+        ServerMessage::Welcome {
+            protocol_version,
+            server_build,
+            next_event_seq,
+        } => {
+            if protocol_version != PROTOCOL_VERSION {
+                let error = ProtocolError::VersionMismatch {
+                    expected: PROTOCOL_VERSION,
+                    received: protocol_version,
+                };
+                connection.send_now(&ClientMessage::ProtocolError(error.clone()));
+                connection.give_up(&error);
+                return;
+            }
+            info!("Handshake complete, server build {}", server_build);
+            connection.state = ConnectionState::Connected;
+
+            // Resume the event stream where the server left off, resending anything it lost.
+            event_sync.acked_seq = next_event_seq;
+            event_sync.sent_seq = next_event_seq;
+        }
+        ServerMessage::MatchEventsAck { next_seq } => {
+            event_sync.acked_seq = event_sync.acked_seq.max(next_seq);
+        }
+        // Another attempt would use the same protocol version.
+        ServerMessage::Rejected(error @ ProtocolError::VersionMismatch { .. }) => {
+            connection.give_up(&error);
+        }
+        ServerMessage::Rejected(error) => {
+            warn!("Coaching server rejected our message: {}", error);
+        }
+        ServerMessage::UpdateRuleSet { .. } | ServerMessage::UpdateBehaviorTree { .. }
+            if connection.state != ConnectionState::Connected =>
+        {
+            warn!("Ignoring AI update received before the handshake");
+            connection.send_now(&ClientMessage::ProtocolError(
+                ProtocolError::HandshakeRequired,
+            ));
+        }
+        ServerMessage::UpdateRuleSet {
+            update_id,
+            target,
+            rule_set,
+        } => {
+            let issues = rule_set.validate(area_map);
+            for issue in issues.iter().filter(|i| i.severity == Severity::Warning) {
+                warn!("Rule set update {}: {}", update_id, issue);
+            }
+            if validation::has_errors(&issues) {
+                let errors: Vec<String> = issues
+                    .iter()
+                    .filter(|issue| issue.severity == Severity::Error)
+                    .map(|issue| issue.to_string())
+                    .collect();
+                let error = ProtocolError::InvalidRuleSet { errors };
+                warn!("Refusing rule set update {}: {}", update_id, error);
+                connection.queue(ClientMessage::ProtocolError(error));
+                return;
+            }
+
+            let applied_to = apply_rule_set(&target, &rule_set, commands, ai_query);
+            connection.queue(ClientMessage::RuleSetApplied {
+                update_id,
+                applied_to,
+                time,
+            });
+        }
+        ServerMessage::UpdateBehaviorTree {
+            update_id,
+            target,
+            tree,
+        } => {
+            let issues = tree.validate(area_map);
+            for issue in issues.iter().filter(|i| i.severity == Severity::Warning) {
+                warn!("Behavior tree update {}: {}", update_id, issue);
+            }
+            if validation::has_errors(&issues) {
+                let errors: Vec<String> = issues
+                    .iter()
+                    .filter(|issue| issue.severity == Severity::Error)
+                    .map(|issue| issue.to_string())
+                    .collect();
+                let error = ProtocolError::InvalidBehaviorTree { errors };
+                warn!("Refusing behavior tree update {}: {}", update_id, error);
+                connection.queue(ClientMessage::ProtocolError(error));
+                return;
+            }
+
+            let applied_to = apply_behavior_tree(&target, &tree, commands, ai_query);
+            connection.queue(ClientMessage::RuleSetApplied {
+                update_id,
+                applied_to,
+                time,
+            });
+        }
+    }
+}
+
+/// Swaps the rule set of every targeted AI, dropping any behavior tree, and returns their IDs.
+fn apply_rule_set(
+    target: &RuleSetTarget,
+    rule_set: &RuleSet,
+    commands: &mut Commands,
+    ai_query: &mut AiQuery,
+) -> Vec<PlayerID> {
+    let mut applied_to = Vec::new();
+
+    for (entity, name, player_id, mut ai_rule_set) in ai_query.iter_mut() {
+        if !target.includes(player_id) {
+            continue;
+        }
+        ai_rule_set.0 = rule_set.clone();
+        commands.entity(entity).remove::<AiBehaviorTree>();
+        applied_to.push(*player_id);
+        info!(
+            "AI {} received new rule set ({} rules)",
+            name,
+            rule_set.rules.len()
+        );
+    }
+
+    applied_to
+}
+
+/// Gives every targeted AI the behavior tree and returns their IDs. Their rule sets are kept
+/// for when the next `UpdateRuleSet` removes the tree.
+fn apply_behavior_tree(
+    target: &RuleSetTarget,
+    tree: &BehaviorTree,
+    commands: &mut Commands,
+    ai_query: &mut AiQuery,
+) -> Vec<PlayerID> {
+    let mut applied_to = Vec::new();
+
+    for (entity, name, player_id, _) in ai_query.iter_mut() {
+        if !target.includes(player_id) {
+            continue;
+        }
+        commands.entity(entity).insert(AiBehaviorTree(tree.clone()));
+        applied_to.push(*player_id);
+        info!(
+            "AI {} received new behavior tree ({} nodes)",
+            name,
+            tree.root.size()
+        );
+    }
+
+    applied_to
+}
+
+fn push_client_updates(
+    mut connection: NonSendMut<ServerConnection>,
+    config: Res<NetworkConfig>,
+    time: Res<Time>,
+    mut timer: Local<f32>,
+    player_query: Query<(&PlayerID, &PlayerStatus)>,
+    match_log: Res<MatchLog>,
+    mut event_sync: ResMut<MatchEventSync>,
+) {
+    *timer += time.delta_secs();
+    if *timer >= config.send_interval {
+        *timer = 0.0;
+
+        for (player_id, status) in player_query.iter() {
+            connection.queue(ClientMessage::UpdatePlayerStatus {
+                player: *player_id,
+                status: status.clone(),
+            });
+        }
+
+        let events = match_log.events_since(event_sync.sent_seq);
+        if connection.state() == ConnectionState::Connected && !events.is_empty() {
+            connection.send_now(&ClientMessage::PushMatchEvents {
+                from_seq: event_sync.sent_seq,
+                events: events.to_vec(),
+            });
+            event_sync.sent_seq = match_log.next_seq();
+        }
+    }
+
+    connection.flush();
+}
+
+fn publish_connection_state(
+    connection: NonSend<ServerConnection>,
+    mut state: ResMut<ConnectionState>,
+) {
+    if *state != connection.state() {
+        *state = connection.state();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> NetworkConfig {
+        NetworkConfig {
+            reconnect_delay: 1.0,
+            max_reconnect_delay: 10.0,
+            max_reconnect_attempts: Some(3),
+            ..default()
+        }
+    }
+
+    /// A message told apart by `n`.
+    fn message(n: u32) -> ClientMessage {
+        ClientMessage::ProtocolError(ProtocolError::UnknownVariant {
+            variant: n.to_string(),
+        })
+    }
+
+    fn numbers(messages: &[ClientMessage]) -> Vec<u32> {
+        messages
+            .iter()
+            .map(|message| match message {
+                ClientMessage::ProtocolError(ProtocolError::UnknownVariant { variant }) => {
+                    variant.parse().unwrap()
+                }
+                _ => unreachable!("tests only queue `message`s"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let config = config();
+        let delays: Vec<f32> = (1..=6).map(|attempt| config.backoff(attempt)).collect();
+        assert_eq!(delays, vec![1.0, 2.0, 4.0, 8.0, 10.0, 10.0]);
+        assert_eq!(config.backoff(u32::MAX), 10.0);
+    }
+
+    #[test]
+    fn lost_connections_are_retried_until_the_limit() {
+        let config = config();
+        let mut connection = ServerConnection::new(4);
+        for attempt in 1..=3 {
+            connection.lost(&config);
+            assert_eq!(
+                connection.state(),
+                ConnectionState::Reconnecting { attempt }
+            );
+            assert_eq!(connection.retry_timer, config.backoff(attempt));
+        }
+        connection.lost(&config);
+        assert_eq!(connection.state(), ConnectionState::Offline);
+    }
+
+    #[test]
+    fn queued_messages_wait_for_the_handshake() {
+        let mut connection = ServerConnection::new(2);
+        for n in 0..3 {
+            connection.queue(message(n));
+        }
+        connection.lost(&config());
+        assert!(connection.take_sendable().is_empty());
+
+        // What `Welcome` does once the connection is back.
+        connection.state = ConnectionState::Connected;
+        assert_eq!(numbers(&connection.take_sendable()), vec![1, 2]);
+        assert!(connection.take_sendable().is_empty());
+    }
+}
@@ -4,6 +4,7 @@ use crate::building::Structure;
 use crate::logging::{GameEvent, MatchLog};
 use crate::pathfinding::{find_path, has_line_of_sight, NavGraph};
 use crate::player_id::PlayerID;
+use crate::simulation::SimulationSet;
 use bevy::prelude::*;
 use std::collections::HashMap;
 
@@ -68,8 +69,13 @@ pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
-            Update,
-            (execute_movement, update_player_visibility, update_inventory),
+            FixedUpdate,
+            (
+                (update_player_visibility, update_inventory)
+                    .chain()
+                    .in_set(SimulationSet::Perception),
+                execute_movement.in_set(SimulationSet::Movement),
+            ),
         );
     }
 }
@@ -277,6 +283,8 @@ fn execute_movement(
     for (mut transform, mut controller) in query.iter_mut() {
         if controller.rotation_delta != 0.0 {
             transform.rotate_y(controller.rotation_delta);
+            // Input may add up over several frames between two ticks, consume it once.
+            controller.rotation_delta = 0.0;
         }
 
         let target_velocity = if controller.input_direction.length_squared() > 0.0 {
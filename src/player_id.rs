@@ -1,14 +1,16 @@
 //! Defines the PlayerID type for uniquely identifying networked players (human or AI).
 
 use bevy::prelude::Component;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 #[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PlayerID(pub u64);
 
 impl PlayerID {
-    /// Creates a new, random PlayerID.
-    pub fn random() -> Self {
-        PlayerID(rand::random())
+    /// Creates a new, random PlayerID. Pass the `SimRng` inside the game, so IDs are the
+    /// same for every run with the same seed.
+    pub fn random(rng: &mut impl Rng) -> Self {
+        PlayerID(rng.random())
     }
 }
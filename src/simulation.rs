@@ -0,0 +1,71 @@
+//! Fixed-timestep, seeded simulation.
+//!
+//! All gameplay systems run in `FixedUpdate`, in the order given by `SimulationSet`, and draw
+//! their randomness from `SimRng`. Given the same seed and the same inputs per tick, a match
+//! produces exactly the same `MatchLog`. Rendering and user input stay in `Update`.
+
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Gameplay ticks per second.
+pub const TICK_RATE: f64 = 60.0;
+
+pub struct SimulationPlugin {
+    seed: u64,
+}
+
+impl SimulationPlugin {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .insert_resource(MatchSeed(self.seed))
+            .insert_resource(SimRng::new(self.seed))
+            .configure_sets(
+                FixedUpdate,
+                (
+                    SimulationSet::Perception,
+                    SimulationSet::Ai,
+                    SimulationSet::Movement,
+                    SimulationSet::Combat,
+                    SimulationSet::World,
+                )
+                    .chain(),
+            );
+
+        info!("Simulation seed: {}", self.seed);
+    }
+}
+
+/// Seed the match was started with, kept for bug reports and replays.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct MatchSeed(pub u64);
+
+/// The only source of randomness gameplay code may use.
+#[derive(Resource)]
+pub struct SimRng(pub StdRng);
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
+
+/// Stages of one gameplay tick. Systems inside a stage are chained too, since parallel
+/// execution order would otherwise leak into the `MatchLog`.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SimulationSet {
+    /// Visibility, area tracking and item pickup.
+    Perception,
+    /// Rule evaluation and path following.
+    Ai,
+    Movement,
+    Combat,
+    /// Resource respawns.
+    World,
+}
@@ -1,6 +1,7 @@
 use crate::arena::{ArenaConfig, ArenaGrid, Collectible};
 use crate::building::{Structure, StructureType};
 use crate::combat::{Enemy, Hp, TurretDirection};
+use crate::network::ConnectionState;
 use crate::player::{Inventory, MovementController};
 use crate::GameState;
 use bevy::input::mouse::AccumulatedMouseMotion;
@@ -46,6 +47,9 @@ pub struct HpText;
 #[derive(Component)]
 pub struct EnemyHpText;
 
+#[derive(Component)]
+pub struct CoachStatusText;
+
 #[derive(Component)]
 pub struct MainCamera {
     pub pitch: f32,
@@ -64,6 +68,7 @@ impl Plugin for UserPlugin {
                 update_hud_counts,
                 update_hud_highlight,
                 update_hp_bar,
+                update_coach_status,
             ),
         )
         .add_systems(Startup, setup_hud)
@@ -118,11 +123,8 @@ fn handle_user_input(
     }
 
     for mut controller in query.iter_mut() {
-        if delta.x != 0.0 {
-            controller.rotation_delta = -delta.x * rotation_scale * 0.05;
-        } else {
-            controller.rotation_delta = 0.0;
-        }
+        // Accumulated until the next gameplay tick applies it.
+        controller.rotation_delta += -delta.x * rotation_scale * 0.05;
 
         let mut direction = Vec3::ZERO;
         if keyboard_input.pressed(KeyCode::KeyW) {
@@ -160,7 +162,7 @@ fn setup_hud(mut commands: Commands) {
             HudContainer,
             Node {
                 width: Val::Px(220.0),
-                height: Val::Px(220.0),
+                height: Val::Px(250.0),
                 left: Val::Px(20.0),
                 top: Val::Px(20.0),
                 flex_direction: FlexDirection::Column,
@@ -311,6 +313,14 @@ fn setup_hud(mut commands: Commands) {
         EnemyHpText,
         ChildOf(panel),
     ));
+
+    commands.spawn((
+        Text::new("Coach: -"),
+        TextFont::from_font_size(16.0),
+        TextColor(Color::WHITE),
+        CoachStatusText,
+        ChildOf(panel),
+    ));
 }
 
 fn handle_build_type_selection(
@@ -584,3 +594,27 @@ fn update_hp_bar(
         }
     }
 }
+
+fn update_coach_status(
+    mut text_query: Query<&mut Text, With<CoachStatusText>>,
+    connection_state: Option<Res<ConnectionState>>,
+) {
+    let Some(connection_state) = connection_state else {
+        return;
+    };
+    if !connection_state.is_changed() {
+        return;
+    }
+
+    let status = match *connection_state {
+        ConnectionState::Connected => "Coach: Connected".to_string(),
+        ConnectionState::Reconnecting { attempt } => {
+            format!("Coach: Reconnecting ({})", attempt)
+        }
+        ConnectionState::Offline => "Coach: Offline".to_string(),
+    };
+
+    for mut text in text_query.iter_mut() {
+        text.0 = status.clone();
+    }
+}
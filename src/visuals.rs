@@ -0,0 +1,226 @@
+//! Meshes, materials and gizmos for the gameplay entities.
+//!
+//! Gameplay systems only spawn logic components and transforms. This plugin attaches the
+//! visuals as soon as those entities appear, so the simulation runs without it under
+//! `MinimalPlugins`.
+
+use crate::arena::{ArenaConfig, Collectible, CollectibleType, Tile, WALL_HEIGHT};
+use crate::building::{Structure, StructureType};
+use crate::combat::{Enemy, Turret, TurretFired};
+use crate::player::Player;
+use crate::player_id::PlayerID;
+use crate::user::User;
+use crate::PLAYER_SIZE;
+use bevy::prelude::*;
+
+pub struct VisualsPlugin;
+
+impl Plugin for VisualsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_visual_assets).add_systems(
+            PostUpdate,
+            (
+                attach_tile_visuals,
+                attach_structure_visuals,
+                attach_collectible_visuals,
+                attach_player_visuals,
+                draw_turret_status,
+                draw_turret_shots,
+            ),
+        );
+    }
+}
+
+/// Shared mesh and material handles, so every entity of a kind reuses the same assets.
+#[derive(Resource)]
+struct VisualAssets {
+    floor_mesh: Handle<Mesh>,
+    floor_material: Handle<StandardMaterial>,
+    wall_mesh: Handle<Mesh>,
+    wall_material: Handle<StandardMaterial>,
+    obstacle_mesh: Handle<Mesh>,
+    obstacle_material: Handle<StandardMaterial>,
+    user_turret: TurretVisuals,
+    ai_turret: TurretVisuals,
+    collectible_mesh: Handle<Mesh>,
+    turret_collectible_material: Handle<StandardMaterial>,
+    obstacle_collectible_material: Handle<StandardMaterial>,
+    player_mesh: Handle<Mesh>,
+    user_material: Handle<StandardMaterial>,
+    enemy_material: Handle<StandardMaterial>,
+    ai_material: Handle<StandardMaterial>,
+}
+
+struct TurretVisuals {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    barrel_mesh: Handle<Mesh>,
+    barrel_material: Handle<StandardMaterial>,
+    /// Barrel position in turret space. North is -Z, so forward is -Z.
+    barrel_offset: Vec3,
+}
+
+fn setup_visual_assets(
+    mut commands: Commands,
+    config: Res<ArenaConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(VisualAssets {
+        floor_mesh: meshes.add(Cuboid::new(config.tile_size, 0.1, config.tile_size)),
+        floor_material: materials.add(Color::srgb(0.3, 0.5, 0.3)),
+        wall_mesh: meshes.add(Cuboid::new(config.tile_size, WALL_HEIGHT, config.tile_size)),
+        wall_material: materials.add(Color::srgb(0.2, 0.2, 0.2)),
+        obstacle_mesh: meshes.add(Cuboid::new(
+            config.tile_size * 0.8,
+            WALL_HEIGHT * 0.8,
+            config.tile_size * 0.8,
+        )),
+        obstacle_material: materials.add(Color::srgb(0.6, 0.3, 0.3)),
+        user_turret: TurretVisuals {
+            mesh: meshes.add(Cylinder::new(1.5, 3.0)),
+            material: materials.add(Color::srgb(0.0, 0.5, 1.0)),
+            barrel_mesh: meshes.add(Cuboid::new(0.5, 0.5, 2.0)),
+            barrel_material: materials.add(Color::srgb(0.2, 0.2, 0.8)),
+            barrel_offset: -Vec3::Z * 2.5 + Vec3::Y * 0.5,
+        },
+        ai_turret: TurretVisuals {
+            mesh: meshes.add(Cuboid::new(1.0, 2.0, 1.0)),
+            material: materials.add(Color::srgb(0.2, 0.2, 0.2)),
+            barrel_mesh: meshes.add(Cuboid::new(0.4, 0.4, 3.0)),
+            barrel_material: materials.add(Color::srgb(0.1, 0.1, 0.1)),
+            barrel_offset: -Vec3::Z * 2.5,
+        },
+        collectible_mesh: meshes.add(Cuboid::new(0.5, 0.5, 0.5)),
+        turret_collectible_material: materials.add(Color::srgb(0.0, 0.0, 1.0)),
+        obstacle_collectible_material: materials.add(Color::srgb(1.0, 1.0, 0.0)),
+        player_mesh: meshes.add(Cuboid::new(PLAYER_SIZE.x, PLAYER_SIZE.y, PLAYER_SIZE.z)),
+        user_material: materials.add(Color::srgb(0.8, 0.7, 0.6)),
+        enemy_material: materials.add(Color::srgb(0.8, 0.2, 0.2)),
+        ai_material: materials.add(Color::srgb(0.2, 0.2, 0.8)),
+    });
+}
+
+fn attach_tile_visuals(
+    mut commands: Commands,
+    assets: Res<VisualAssets>,
+    tile_query: Query<Entity, Added<Tile>>,
+) {
+    for entity in tile_query.iter() {
+        commands.entity(entity).insert((
+            Mesh3d(assets.floor_mesh.clone()),
+            MeshMaterial3d(assets.floor_material.clone()),
+        ));
+    }
+}
+
+fn attach_structure_visuals(
+    mut commands: Commands,
+    assets: Res<VisualAssets>,
+    structure_query: Query<(Entity, &Structure, Option<&Turret>), Added<Structure>>,
+    user_query: Query<&PlayerID, With<User>>,
+) {
+    let user_id = user_query.iter().next().copied();
+
+    for (entity, structure, turret) in structure_query.iter() {
+        match (structure.ty, turret) {
+            (StructureType::Turret, Some(turret)) => {
+                let visuals = if Some(turret.owner) == user_id {
+                    &assets.user_turret
+                } else {
+                    &assets.ai_turret
+                };
+                commands
+                    .entity(entity)
+                    .insert((
+                        Mesh3d(visuals.mesh.clone()),
+                        MeshMaterial3d(visuals.material.clone()),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            Mesh3d(visuals.barrel_mesh.clone()),
+                            MeshMaterial3d(visuals.barrel_material.clone()),
+                            Transform::from_translation(visuals.barrel_offset),
+                        ));
+                    });
+            }
+            (StructureType::Wall, _) => {
+                commands.entity(entity).insert((
+                    Mesh3d(assets.wall_mesh.clone()),
+                    MeshMaterial3d(assets.wall_material.clone()),
+                ));
+            }
+            _ => {
+                commands.entity(entity).insert((
+                    Mesh3d(assets.obstacle_mesh.clone()),
+                    MeshMaterial3d(assets.obstacle_material.clone()),
+                ));
+            }
+        }
+    }
+}
+
+fn attach_collectible_visuals(
+    mut commands: Commands,
+    assets: Res<VisualAssets>,
+    collectible_query: Query<(Entity, &Collectible), Added<Collectible>>,
+) {
+    for (entity, collectible) in collectible_query.iter() {
+        let material = match collectible.ty {
+            CollectibleType::Turret => assets.turret_collectible_material.clone(),
+            CollectibleType::Obstacle => assets.obstacle_collectible_material.clone(),
+        };
+        commands.entity(entity).insert((
+            Mesh3d(assets.collectible_mesh.clone()),
+            MeshMaterial3d(material),
+        ));
+    }
+}
+
+fn attach_player_visuals(
+    mut commands: Commands,
+    assets: Res<VisualAssets>,
+    player_query: Query<(Entity, Has<User>, Has<Enemy>), Added<Player>>,
+) {
+    for (entity, is_user, is_enemy) in player_query.iter() {
+        let material = if is_user {
+            assets.user_material.clone()
+        } else if is_enemy {
+            assets.enemy_material.clone()
+        } else {
+            assets.ai_material.clone()
+        };
+        commands
+            .entity(entity)
+            .insert((Mesh3d(assets.player_mesh.clone()), MeshMaterial3d(material)));
+    }
+}
+
+fn draw_turret_status(
+    mut gizmos: Gizmos,
+    time: Res<Time<Fixed>>,
+    turret_query: Query<(&Transform, &Turret)>,
+) {
+    // `last_shot` is gameplay time, kept by the fixed clock.
+    let current_time = time.elapsed_secs();
+
+    for (transform, turret) in turret_query.iter() {
+        // Red box above the turret while it reloads, green once it can fire again.
+        let color = if turret.is_active(current_time) {
+            Color::srgb(0.0, 1.0, 0.0)
+        } else {
+            Color::srgb(1.0, 0.0, 0.0)
+        };
+        gizmos.cube(
+            Transform::from_translation(transform.translation + Vec3::Y * 2.5)
+                .with_scale(Vec3::splat(0.5)),
+            color,
+        );
+    }
+}
+
+fn draw_turret_shots(mut gizmos: Gizmos, mut shots: MessageReader<TurretFired>) {
+    for shot in shots.read() {
+        gizmos.line(shot.from, shot.to, Color::srgb(1.0, 1.0, 0.0));
+    }
+}